@@ -0,0 +1,203 @@
+use anyhow::{bail, Context, Result};
+use dialoguer::theme::ColorfulTheme;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Every decision the installer can ask about, paired with the CLI flag that answers it.
+pub const KEYS: &[(&str, &str)] = &[
+    ("game", "--game"),
+    ("store", "--store"),
+    ("steam_install", "--steam-install"),
+    ("steam_user", "--steam-user"),
+    ("edition", "--edition"),
+    ("proton", "--proton"),
+    ("version", "--version"),
+    ("api_url", "--api-url"),
+    ("repo", "--repo"),
+    ("asset_pattern", "--asset-pattern"),
+    ("token", "--token"),
+    ("install_path", "--install-path"),
+    ("installer", "--installer"),
+    ("game_dir", "--game-dir"),
+    ("prefix", "--prefix"),
+    ("wine", "--wine"),
+    ("canary", "--canary"),
+    ("desktop_shortcut", "--desktop-shortcut"),
+    ("steam_shortcut", "--steam-shortcut"),
+    ("artwork", "--artwork"),
+    ("controller_config", "--controller-config"),
+    ("close_steam", "--close-steam"),
+    ("channel", "`stable` or `canary`"),
+    ("download", "--download"),
+];
+
+static ANSWERS: OnceLock<Answers> = OnceLock::new();
+
+/// Pre-supplied answers to the installer's prompts, collected from an answer file and CLI flags.
+#[derive(Debug, Default, Clone)]
+pub struct Answers {
+    values: BTreeMap<String, toml::Value>,
+    non_interactive: bool,
+}
+
+impl Answers {
+    pub fn new(non_interactive: bool) -> Self {
+        Answers {
+            values: BTreeMap::new(),
+            non_interactive,
+        }
+    }
+
+    /// Load answers from a TOML file, rejecting any key the installer doesn't know about.
+    pub fn from_file(path: &Path, non_interactive: bool) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read answer file {}", path.display()))?;
+        let table: toml::Table = toml::from_str(&contents)
+            .with_context(|| format!("Couldn't parse answer file {}", path.display()))?;
+
+        let mut answers = Answers::new(non_interactive);
+        for (key, value) in table {
+            if !KEYS.iter().any(|(k, _)| *k == key) {
+                bail!(
+                    "Unknown key `{key}` in answer file {}. Valid keys: {}",
+                    path.display(),
+                    KEYS.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(", ")
+                );
+            }
+            answers.values.insert(key, value);
+        }
+        Ok(answers)
+    }
+
+    /// Set an answer, overriding anything loaded from the answer file.
+    pub fn set(&mut self, key: &str, value: impl Into<toml::Value>) {
+        self.values.insert(key.to_string(), value.into());
+    }
+
+    pub fn get_str(&self, key: &str) -> Result<Option<String>> {
+        match self.values.get(key) {
+            None => Ok(None),
+            Some(toml::Value::String(s)) => Ok(Some(s.clone())),
+            Some(toml::Value::Integer(i)) => Ok(Some(i.to_string())),
+            Some(other) => bail!("Answer `{key}` must be a string, got `{other}`"),
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        match self.values.get(key) {
+            None => Ok(None),
+            Some(toml::Value::Boolean(b)) => Ok(Some(*b)),
+            Some(other) => bail!("Answer `{key}` must be true or false, got `{other}`"),
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        !self.non_interactive
+    }
+}
+
+/// Install the answers used by every prompt for the rest of the process.
+pub fn init(answers: Answers) {
+    if ANSWERS.set(answers).is_err() {
+        log::warn!("Answers were already initialized, ignoring");
+    }
+}
+
+pub fn get() -> &'static Answers {
+    ANSWERS.get_or_init(Answers::default)
+}
+
+/// Error for a decision that has no answer while running non-interactively.
+pub fn missing(key: &str) -> anyhow::Error {
+    let flag = KEYS
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map_or("", |(_, flag)| flag);
+    anyhow::anyhow!(
+        "Missing answer for `{key}` in non-interactive mode (pass {flag} or set `{key}` in the answer file)"
+    )
+}
+
+/// Pick one of `items`, using the answer for `key` if one was given.
+///
+/// `ids` holds the accepted answer for each item; matching is case-insensitive.
+pub fn select<T: ToString>(
+    key: &str,
+    prompt: &str,
+    items: &[T],
+    ids: &[&str],
+    default: usize,
+) -> Result<usize> {
+    let answers = get();
+    if let Some(answer) = answers.get_str(key)? {
+        return ids
+            .iter()
+            .position(|id| id.eq_ignore_ascii_case(&answer))
+            .with_context(|| {
+                format!(
+                    "Invalid answer `{answer}` for `{key}`. Expected one of: {}",
+                    ids.join(", ")
+                )
+            });
+    }
+    if !answers.is_interactive() {
+        return Err(missing(key));
+    }
+
+    dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .items(items)
+        .interact()
+        .with_context(|| format!("Selection failed for `{key}`"))
+}
+
+/// Ask a Yes/No question, using the answer for `key` if one was given.
+pub fn confirm(key: &str, prompt: &str) -> Result<bool> {
+    confirm_prompted(key, prompt).map(|(answer, _)| answer)
+}
+
+/// Like [`confirm`], also reporting whether a prompt was drawn, e.g. to clear it afterwards.
+pub fn confirm_prompted(key: &str, prompt: &str) -> Result<(bool, bool)> {
+    let answers = get();
+    if let Some(answer) = answers.get_bool(key)? {
+        return Ok((answer, false));
+    }
+    if !answers.is_interactive() {
+        return Err(missing(key));
+    }
+
+    let choices = &["Yes", "No"];
+    let selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(0) // Default to "Yes"
+        .items(choices)
+        .interact()
+        .with_context(|| format!("Selection failed for `{key}`"))?;
+    Ok((selection == 0, true))
+}
+
+/// Ask for a path, using the answer for `key` if one was given.
+pub fn path(key: &str, prompt: &str) -> Result<PathBuf> {
+    let answers = get();
+    if let Some(answer) = answers.get_str(key)? {
+        return Ok(PathBuf::from(answer));
+    }
+    if !answers.is_interactive() {
+        return Err(missing(key));
+    }
+
+    let input: String = dialoguer::Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .interact_text()
+        .with_context(|| format!("Input failed for `{key}`"))?;
+    // Allow ~ since the path is typed rather than picked
+    let path = match input.trim().strip_prefix("~/") {
+        Some(rest) => home::home_dir().context("Couldn't find $HOME?")?.join(rest),
+        None => PathBuf::from(input.trim()),
+    };
+    Ok(path)
+}
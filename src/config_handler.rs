@@ -1,78 +1,904 @@
-use anyhow::{Context, Result};
-use std::{collections::HashMap, env, path::PathBuf};
+use crate::editions::{self, Drive, Edition, Store};
+use crate::manifest::{self, FileKind};
+use crate::mod_loader;
+use crate::settings_xml::Catalog;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fmt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use toml_edit::{DocumentMut, ImDocument, Item, Value};
 
-static CONFIG_NAME: &str = "MateriaForge.toml";
+pub const CONFIG_NAME: &str = "MateriaForge.toml";
 
-fn config_path() -> Result<PathBuf> {
-    let current_bin = env::current_exe().context("Failed to get binary path")?;
-    let current_dir = current_bin
-        .parent()
-        .context("Failed to get binary directory")?;
-    Ok(current_dir.join(CONFIG_NAME))
+/// Name of the user's defaults file in `$XDG_CONFIG_HOME/MateriaForge`
+pub const USER_CONFIG_NAME: &str = "config.toml";
+
+/// Version of the `MateriaForge.toml` layout written by this build. Files without a
+/// `schema_version` are version 1.
+pub const SCHEMA_VERSION: i64 = 2;
+
+/// Upgrades from one schema version to the next; `MIGRATIONS[0]` turns version 1 into 2
+const MIGRATIONS: &[fn(&mut DocumentMut) -> Result<()>] = &[migrate_v1];
+
+static CURRENT: OnceLock<Config> = OnceLock::new();
+static USER: OnceLock<(PathBuf, UserConfig)> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallType {
+    #[default]
+    Steam,
+    Gog,
+    Lutris,
+    Custom,
 }
 
-pub fn write(config: HashMap<&str, String>, env_vars: HashMap<&str, String>) -> Result<()> {
-    let current_bin = env::current_exe()?;
-    let current_dir = current_bin
-        .parent()
-        .expect("Failed to get current directory");
-    let toml_path = current_dir.join(CONFIG_NAME);
+impl InstallType {
+    const ALL: [InstallType; 4] = [
+        InstallType::Steam,
+        InstallType::Gog,
+        InstallType::Lutris,
+        InstallType::Custom,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstallType::Steam => "steam",
+            InstallType::Gog => "gog",
+            InstallType::Lutris => "lutris",
+            InstallType::Custom => "custom",
+        }
+    }
+}
 
-    let mut table = toml::value::Table::new();
-    for (k, v) in &config {
-        table.insert(k.to_string(), toml::Value::String(v.clone()));
+impl fmt::Display for InstallType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
     }
-    if !env_vars.is_empty() {
-        let mut env_table = toml::value::Table::new();
-        for (k, v) in &env_vars {
-            env_table.insert(k.to_string(), toml::Value::String(v.clone()));
+}
+
+/// Update channel of the mod loader
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Canary,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Canary => "canary",
         }
-        table.insert("env".to_string(), toml::Value::Table(env_table));
     }
-    let toml_string = toml::to_string(&table)?;
-    std::fs::write(toml_path, toml_string)?;
 
-    Ok(())
+    /// Value of `FFNxUpdateChannel` and `AppUpdateChannel` in the loader's `settings.xml`
+    pub fn update_channel(&self) -> &'static str {
+        match self {
+            Channel::Stable => "Stable",
+            Channel::Canary => "Canary",
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "stable" => Ok(Channel::Stable),
+            "canary" => Ok(Channel::Canary),
+            _ => Err(format!("Unknown channel '{s}', expected stable or canary")),
+        }
+    }
+}
+
+/// Defaults shared by every install, from the user's `config.toml`. Settings in an install's
+/// own `MateriaForge.toml` take priority.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    /// Preferred Proton version, by Steam's compat tool name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch_args: Option<String>,
+    /// Directory new installs go into, as `<install_root>/<loader name>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install_root: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+    /// Mod catalogs every install is subscribed to, on top of the loader's own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub catalogs: Vec<Catalog>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Where each loader's releases come from, by [`mod_loader::ModLoader::id`], e.g.
+    /// `[release.7th_heaven]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub release: BTreeMap<String, ReleaseConfig>,
+}
+
+/// A `[release.<loader>]` table of the user's `config.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseConfig {
+    /// Release API to download from, e.g. a Gitea or Forgejo mirror
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// `owner/name` of the repo to download releases from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// Regex picking the installer among a release's assets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_pattern: Option<String>,
+    /// Bearer token for `api_url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Contents of the `MateriaForge.toml` next to an installed launcher
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub schema_version: i64,
+    #[serde(rename = "type")]
+    pub install_type: InstallType,
+    /// [`mod_loader::ModLoader::id`] of the installed loader
+    pub loader: String,
+    pub app_id: u32,
+    /// Release tag of the installed loader
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Update channel of the installed loader; stable when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steam_dir: Option<PathBuf>,
+    /// Proton version override, by Steam's compat tool name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch_args: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_dir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wine: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn new(install_type: InstallType, loader: &str, app_id: u32) -> Self {
+        Config {
+            schema_version: SCHEMA_VERSION,
+            install_type,
+            loader: loader.to_string(),
+            app_id,
+            version: None,
+            channel: None,
+            steam_dir: None,
+            runner: None,
+            launch_args: None,
+            game_dir: None,
+            prefix: None,
+            wine: None,
+            env: BTreeMap::new(),
+        }
+    }
+
+    /// The edition this install is for, as the mod loader sees it
+    pub fn edition(&self) -> Result<Edition> {
+        let mut edition = editions::by_app_id(self.app_id)?.clone();
+        // Only Steam's Proton maps the S: drive, anything else sees the game through Z:
+        if self.install_type == InstallType::Custom {
+            edition.drive = Drive::Z;
+        }
+        Ok(edition)
+    }
+
+    /// Fill in whatever this install doesn't set from the user's defaults
+    pub fn with_defaults(mut self, user: &UserConfig) -> Self {
+        if self.runner.is_none() {
+            self.runner.clone_from(&user.runner);
+        }
+        if self.launch_args.is_none() {
+            self.launch_args.clone_from(&user.launch_args);
+        }
+        for (name, value) in &user.env {
+            self.env
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        self
+    }
+
+    /// Write the config to `dir`, replacing any existing file
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(CONFIG_NAME);
+        let toml_string = toml::to_string(self).context("Couldn't serialize config")?;
+        std::fs::write(&path, toml_string)
+            .with_context(|| format!("Couldn't write {}", path.display()))
+    }
 }
 
-pub fn read_value(key: &str) -> Result<String> {
+/// The directory of the running binary, where its own config lives
+pub fn exe_dir() -> Result<PathBuf> {
     let current_bin = env::current_exe().context("Failed to get binary path")?;
-    let current_dir = current_bin
+    Ok(current_bin
         .parent()
-        .context("Failed to get binary directory")?;
-    let toml_path = current_dir.join(CONFIG_NAME);
+        .context("Failed to get binary directory")?
+        .to_path_buf())
+}
+
+/// Load, migrate and validate the config in `dir`
+pub fn load(dir: &Path) -> Result<Config> {
+    let path = dir.join(CONFIG_NAME);
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    let contents = migrate(&path, contents)?;
+    check(&path, &contents)
+}
+
+/// Validate `contents` as the config at `path` and parse it
+pub fn check(path: &Path, contents: &str) -> Result<Config> {
+    validate(path, contents)?;
+    toml::from_str(contents).with_context(|| format!("Couldn't parse {}", path.display()))
+}
+
+/// An install's `MateriaForge.toml` opened for editing. Changes keep the comments and order of
+/// everything they don't touch, and are validated before being written.
+pub struct ConfigFile {
+    pub path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl ConfigFile {
+    /// Open the config in `dir`, migrating it first if it's from an older version
+    pub fn open(dir: &Path) -> Result<Self> {
+        load(dir)?;
+        let path = dir.join(CONFIG_NAME);
+        let doc = std::fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?
+            .parse()
+            .with_context(|| format!("Couldn't parse {}", path.display()))?;
+        Ok(ConfigFile { path, doc })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Item> {
+        self.doc.get(key)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = match key {
+            "schema_version" => bail!("`schema_version` is managed by MateriaForge"),
+            "env" => bail!("Use `config env-set` for environment variables"),
+            "app_id" => Value::from(
+                value
+                    .parse::<i64>()
+                    .with_context(|| format!("`app_id` '{value}' isn't a number"))?,
+            ),
+            "channel" => Value::from(
+                value
+                    .parse::<Channel>()
+                    .map_err(anyhow::Error::msg)?
+                    .as_str(),
+            ),
+            _ => Value::from(value),
+        };
+        replace(self.doc.as_table_mut(), key, value);
+        Ok(())
+    }
+
+    /// Remove `key`, returning whether it was set
+    pub fn unset(&mut self, key: &str) -> Result<bool> {
+        if key == "schema_version" {
+            bail!("`schema_version` is managed by MateriaForge");
+        }
+        Ok(self.doc.remove(key).is_some())
+    }
+
+    pub fn env_set(&mut self, name: &str, value: &str) -> Result<()> {
+        let env = self
+            .doc
+            .entry("env")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .context("`env` must be a table")?;
+        replace(env, name, Value::from(value));
+        Ok(())
+    }
+
+    /// Remove the environment variable `name`, returning whether it was set
+    pub fn env_unset(&mut self, name: &str) -> bool {
+        self.doc
+            .get_mut("env")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|env| env.remove(name))
+            .is_some()
+    }
+
+    /// The config as it would be saved, if it's valid
+    pub fn config(&self) -> Result<Config> {
+        check(&self.path, &self.doc.to_string())
+    }
+
+    /// Write the file if it's valid, recording the write in the manifest being recorded
+    pub fn save(&self) -> Result<()> {
+        self.config()?;
+        manifest::write(
+            &self.path,
+            self.doc.to_string().as_bytes(),
+            FileKind::Resource,
+        )
+    }
+}
+
+/// Set `key` in `table`, keeping the comments around an existing value
+fn replace(table: &mut dyn toml_edit::TableLike, key: &str, mut value: Value) {
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(old) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        None => {
+            table.insert(key, Item::Value(value));
+        }
+    }
+}
+
+/// The config next to the running binary merged over the user's defaults, loaded once
+pub fn current() -> Result<&'static Config> {
+    if let Some(config) = CURRENT.get() {
+        return Ok(config);
+    }
+    let config = load(&exe_dir()?)?.with_defaults(user());
+    Ok(CURRENT.get_or_init(|| config))
+}
+
+/// Default location of the user's defaults file
+pub fn user_config_path() -> Result<PathBuf> {
+    Ok(xdg::BaseDirectories::with_prefix("MateriaForge")
+        .get_config_home()
+        .context("Couldn't get config_home")?
+        .join(USER_CONFIG_NAME))
+}
+
+/// The user's defaults from `path`, or from the default location if it exists
+fn load_user(path: Option<&Path>) -> Result<(PathBuf, UserConfig)> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let path = user_config_path()?;
+            if !path.is_file() {
+                return Ok((path, UserConfig::default()));
+            }
+            path
+        }
+    };
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    let config =
+        toml::from_str(&contents).with_context(|| format!("Couldn't parse {}", path.display()))?;
+    Ok((path, config))
+}
+
+/// Load the user's defaults for the rest of the process, from `path` instead of the default
+/// location if given
+pub fn init_user(path: Option<&Path>) -> Result<()> {
+    let user = load_user(path)?;
+    if USER.set(user).is_err() {
+        log::warn!("User config was already loaded, ignoring");
+    }
+    Ok(())
+}
 
-    let toml_string = std::fs::read_to_string(toml_path).context("Couldn't read TOML")?;
-    let toml_value: toml::Value =
-        toml::from_str(&toml_string).context("Couldn't deserialize TOML")?;
+fn user_entry() -> &'static (PathBuf, UserConfig) {
+    USER.get_or_init(|| {
+        load_user(None).unwrap_or_else(|e| {
+            log::warn!("Ignoring user config: {e:#}");
+            (
+                user_config_path().unwrap_or_default(),
+                UserConfig::default(),
+            )
+        })
+    })
+}
 
-    let value = toml_value
-        .get(key)
-        .with_context(|| format!("Couldn't find {key} key in {CONFIG_NAME}"))?
-        .as_str()
-        .with_context(|| format!("{key} value is not a string"))?
-        .to_string();
+/// The user's defaults, empty if they have none
+pub fn user() -> &'static UserConfig {
+    &user_entry().1
+}
 
-    Ok(value.to_string())
+/// The file [`user`] was loaded from, which may not exist
+pub fn user_path() -> &'static Path {
+    &user_entry().0
 }
 
-pub fn read_env_vars() -> HashMap<String, String> {
-    let mut env_vars = HashMap::new();
-    let Ok(toml_path) = config_path() else {
-        return env_vars;
+/// Every setting in effect for the install in `dir`, or only the user's defaults without one,
+/// keyed by dotted name (e.g. `env.NAME`) with the file it comes from. Tokens are masked.
+pub fn effective(dir: Option<&Path>) -> Result<BTreeMap<String, (toml::Value, PathBuf)>> {
+    let (user_path, user) = user_entry();
+    let install = match dir {
+        Some(dir) => Some((load(dir)?, dir.join(CONFIG_NAME))),
+        None => None,
     };
-    let Ok(toml_string) = std::fs::read_to_string(toml_path) else {
-        return env_vars;
+    layered(
+        (user, user_path),
+        install
+            .as_ref()
+            .map(|(config, path)| (config, path.as_path())),
+    )
+}
+
+/// The settings of `user` overlaid with those of `install`, each with the file it comes from
+fn layered(
+    user: (&UserConfig, &Path),
+    install: Option<(&Config, &Path)>,
+) -> Result<BTreeMap<String, (toml::Value, PathBuf)>> {
+    let mut layers = vec![(toml::Value::try_from(user.0)?, user.1)];
+    if let Some((config, path)) = install {
+        layers.push((toml::Value::try_from(config)?, path));
+    }
+
+    let mut settings = BTreeMap::new();
+    for (layer, source) in layers {
+        let toml::Value::Table(table) = layer else {
+            continue;
+        };
+        flatten(&mut settings, "", table, source);
+    }
+    Ok(settings)
+}
+
+/// Insert every value of `table` into `settings` under `prefix`, descending into tables
+fn flatten(
+    settings: &mut BTreeMap<String, (toml::Value, PathBuf)>,
+    prefix: &str,
+    table: toml::Table,
+    source: &Path,
+) {
+    for (key, value) in table {
+        let name = format!("{prefix}{key}");
+        match value {
+            toml::Value::Table(table) => flatten(settings, &format!("{name}."), table, source),
+            _ if key == "token" => {
+                let masked = toml::Value::String("****".to_string());
+                settings.insert(name, (masked, source.to_path_buf()));
+            }
+            value => {
+                settings.insert(name, (value, source.to_path_buf()));
+            }
+        }
+    }
+}
+
+/// Bring an older file up to [`SCHEMA_VERSION`], saving the result so it only happens once
+fn migrate(path: &Path, contents: String) -> Result<String> {
+    let mut doc: DocumentMut = contents
+        .parse()
+        .with_context(|| format!("Couldn't parse {}", path.display()))?;
+    let version = match doc.get("schema_version") {
+        None => 1,
+        Some(item) => item
+            .as_integer()
+            .context("`schema_version` must be an integer")?,
     };
-    let Ok(toml_value) = toml_string.parse::<toml::Value>() else {
-        return env_vars;
+    if version < 1 {
+        bail!(
+            "{} has schema_version {version}, but versions start at 1",
+            path.display()
+        );
+    }
+    if version > SCHEMA_VERSION {
+        bail!(
+            "{} has schema_version {version}, but this MateriaForge only understands up to {SCHEMA_VERSION}. Update MateriaForge.",
+            path.display()
+        );
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(contents);
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip((version - 1) as usize) {
+        log::info!(
+            "Migrating {} from schema {} to {}",
+            path.display(),
+            from + 1,
+            from + 2
+        );
+        migration(&mut doc)?;
+    }
+    doc["schema_version"] = toml_edit::value(SCHEMA_VERSION);
+
+    let migrated = doc.to_string();
+    if let Err(e) = std::fs::write(path, &migrated) {
+        log::warn!("Couldn't save migrated {}: {e}", path.display());
+    }
+    Ok(migrated)
+}
+
+/// Version 1 stored every value as a string and relied on defaults for `type` and `loader`
+fn migrate_v1(doc: &mut DocumentMut) -> Result<()> {
+    if !doc.contains_key("type") {
+        doc["type"] = toml_edit::value("steam");
+    }
+    if !doc.contains_key("loader") {
+        doc["loader"] = toml_edit::value("7th_heaven");
+    }
+    if let Some(app_id) = doc.get("app_id").and_then(Item::as_str) {
+        let app_id: i64 = app_id
+            .parse()
+            .with_context(|| format!("`app_id` '{app_id}' isn't a number"))?;
+        replace(doc.as_table_mut(), "app_id", Value::from(app_id));
+    }
+    // GOG installs didn't record which edition they were for
+    if !doc.contains_key("app_id") && doc["type"].as_str() == Some("gog") {
+        let loader = doc["loader"].as_str().unwrap_or_default().to_string();
+        if let Some(edition) = editions::for_loader(&loader, Store::Gog)?.first() {
+            doc["app_id"] = toml_edit::value(i64::from(edition.app_id));
+        }
+    }
+    Ok(())
+}
+
+/// Check every key of the file, reporting all problems at once with their line numbers
+fn validate(path: &Path, contents: &str) -> Result<()> {
+    let doc = ImDocument::parse(contents)
+        .with_context(|| format!("Couldn't parse {}", path.display()))?;
+    let line = |span: Option<std::ops::Range<usize>>| {
+        span.map_or(0, |span| contents[..span.start].matches('\n').count() + 1)
     };
-    if let Some(env_table) = toml_value.get("env").and_then(|v| v.as_table()) {
-        for (key, value) in env_table {
-            if let Some(val_str) = value.as_str() {
-                env_vars.insert(key.clone(), val_str.to_string());
+
+    let mut errors = Vec::new();
+    for (key, item) in doc.iter() {
+        let at = format!("line {}", line(doc.key(key).and_then(|key| key.span())));
+        let expect_str = |errors: &mut Vec<String>| {
+            if !item.is_str() {
+                errors.push(format!("{at}: `{key}` must be a string"));
+            }
+        };
+        match key {
+            "schema_version" | "app_id" => {
+                let value = item.as_integer().unwrap_or(-1);
+                if !item.is_integer() {
+                    errors.push(format!("{at}: `{key}` must be an integer"));
+                } else if key == "app_id" && u32::try_from(value).is_err() {
+                    errors.push(format!("{at}: `app_id` is out of range"));
+                } else if key == "schema_version" && !(1..=SCHEMA_VERSION).contains(&value) {
+                    errors.push(format!(
+                        "{at}: `schema_version` must be between 1 and {SCHEMA_VERSION}"
+                    ));
+                }
             }
+            "type" => match item.as_str() {
+                Some(value) if InstallType::ALL.iter().any(|t| t.as_str() == value) => {}
+                _ => errors.push(format!(
+                    "{at}: `type` must be one of {}",
+                    InstallType::ALL.map(|t| t.as_str()).join(", ")
+                )),
+            },
+            "channel" => match item.as_str() {
+                Some("stable" | "canary") => {}
+                _ => errors.push(format!("{at}: `channel` must be one of stable, canary")),
+            },
+            "loader" => match item.as_str() {
+                Some(value) if mod_loader::from_id(value).is_some() => {}
+                Some(value) => errors.push(format!("{at}: unknown loader `{value}`")),
+                None => errors.push(format!("{at}: `loader` must be a string")),
+            },
+            "version" | "steam_dir" | "runner" | "launch_args" | "game_dir" | "prefix" | "wine" => {
+                expect_str(&mut errors)
+            }
+            "env" => match item.as_table_like() {
+                Some(table) => {
+                    for (name, value) in table.iter() {
+                        if !value.is_str() {
+                            let span = table.get_key_value(name).and_then(|(k, _)| k.span());
+                            errors.push(format!(
+                                "line {}: env var `{name}` must be a string",
+                                line(span)
+                            ));
+                        }
+                    }
+                }
+                None => errors.push(format!("{at}: `env` must be a table")),
+            },
+            _ => errors.push(format!("{at}: unknown key `{key}`")),
         }
     }
-    env_vars
+
+    if !doc.contains_key("schema_version") {
+        errors.push("missing `schema_version`".to_string());
+    }
+    let install_type = doc.get("type").and_then(Item::as_str).unwrap_or("steam");
+    let mut required = vec!["type", "loader", "app_id"];
+    match install_type {
+        "steam" => required.push("steam_dir"),
+        "custom" => required.extend(["game_dir", "prefix", "wine"]),
+        _ => {}
+    }
+    for key in required {
+        if !doc.contains_key(key) {
+            errors.push(format!(
+                "missing `{key}`, which is required for type = \"{install_type}\""
+            ));
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("{} is invalid:\n  {}", path.display(), errors.join("\n  "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_rejects_versions_before_1() {
+        let path = Path::new("MateriaForge.toml");
+        let error = migrate(path, "schema_version = 0\n".to_string()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "MateriaForge.toml has schema_version 0, but versions start at 1"
+        );
+        assert!(migrate(path, "schema_version = -3\n".to_string()).is_err());
+        assert!(migrate(path, "schema_version = \"2\"\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn migrate_v1_keeps_comments() {
+        // A real path, so the migration can save its result
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_NAME);
+        let v1 = "# Written by hand\napp_id = \"39140\" # FF7 (2013)\nsteam_dir = \"/steam\"\n";
+        let migrated = migrate(&path, v1.to_string()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), migrated);
+
+        assert_eq!(
+            migrated,
+            "# Written by hand\napp_id = 39140 # FF7 (2013)\nsteam_dir = \"/steam\"\n\
+             type = \"steam\"\nloader = \"7th_heaven\"\nschema_version = 2\n"
+        );
+        let config = check(&path, &migrated).unwrap();
+        assert_eq!(config.app_id, 39140);
+        assert_eq!(config.install_type, InstallType::Steam);
+    }
+
+    #[test]
+    fn migrate_v1_finds_the_gog_edition() {
+        let mut doc: DocumentMut = "type = \"gog\"\n".parse().unwrap();
+        migrate_v1(&mut doc).unwrap();
+        assert_eq!(doc["app_id"].as_integer(), Some(1698970154));
+        assert_eq!(doc["loader"].as_str(), Some("7th_heaven"));
+
+        let mut doc: DocumentMut = "app_id = \"FF7\"\n".parse().unwrap();
+        assert!(migrate_v1(&mut doc).is_err());
+    }
+
+    #[test]
+    fn current_version_is_left_alone() {
+        let contents = "schema_version = 2 # current\n".to_string();
+        let path = Path::new("/nonexistent/MateriaForge.toml");
+        assert_eq!(migrate(path, contents.clone()).unwrap(), contents);
+    }
+
+    #[test]
+    fn validate_reports_every_error_with_its_line() {
+        let contents = "\
+type = \"steam\"
+loader = \"7th_heaven\"
+# comment
+app_id = 39140
+schema_version = 0
+channel = \"beta\"
+colour = \"red\"
+
+[env]
+DXVK_HUD = 1
+";
+        let error = validate(Path::new("MateriaForge.toml"), contents).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "MateriaForge.toml is invalid:
+  line 5: `schema_version` must be between 1 and 2
+  line 6: `channel` must be one of stable, canary
+  line 7: unknown key `colour`
+  line 10: env var `DXVK_HUD` must be a string
+  missing `steam_dir`, which is required for type = \"steam\""
+        );
+    }
+
+    #[test]
+    fn validate_requires_schema_version() {
+        let contents = "type = \"gog\"\nloader = \"7th_heaven\"\napp_id = 1698970154\n";
+        let error = validate(Path::new("MateriaForge.toml"), contents).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "MateriaForge.toml is invalid:\n  missing `schema_version`"
+        );
+        let contents = format!("schema_version = 2\n{contents}");
+        assert!(validate(Path::new("MateriaForge.toml"), &contents).is_ok());
+    }
+
+    #[test]
+    fn tokens_are_masked() {
+        let user: toml::Table = toml::from_str(
+            "runner = \"proton_9\"\n[release.7th_heaven]\nrepo = \"mirrors/7th-Heaven\"\ntoken = \"secret\"\n",
+        )
+        .unwrap();
+        let mut settings = BTreeMap::new();
+        flatten(&mut settings, "", user, Path::new("config.toml"));
+
+        let values: Vec<_> = settings
+            .iter()
+            .map(|(key, (value, _))| format!("{key} = {value}"))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                "release.7th_heaven.repo = \"mirrors/7th-Heaven\"",
+                "release.7th_heaven.token = \"****\"",
+                "runner = \"proton_9\"",
+            ]
+        );
+    }
+
+    fn user_defaults() -> UserConfig {
+        toml::from_str(
+            "runner = \"proton_9\"\nlaunch_args = \"--debug\"\n\
+             [env]\nDXVK_HUD = \"fps\"\nMANGOHUD = \"1\"\n",
+        )
+        .unwrap()
+    }
+
+    fn install_config() -> Config {
+        let mut config = Config::new(InstallType::Steam, "7th_heaven", 39140);
+        config.steam_dir = Some(PathBuf::from("/steam"));
+        config.runner = Some("GE-Proton9-20".to_string());
+        config.env.insert("DXVK_HUD".to_string(), "0".to_string());
+        config
+    }
+
+    #[test]
+    fn install_overrides_user_defaults() {
+        let config = install_config().with_defaults(&user_defaults());
+        assert_eq!(config.runner.as_deref(), Some("GE-Proton9-20"));
+        assert_eq!(config.launch_args.as_deref(), Some("--debug"));
+        assert_eq!(
+            config.env,
+            BTreeMap::from([
+                ("DXVK_HUD".to_string(), "0".to_string()),
+                ("MANGOHUD".to_string(), "1".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn effective_shows_where_each_setting_comes_from() {
+        let user = user_defaults();
+        let install = install_config();
+        let (user_path, install_path) = (Path::new("config.toml"), Path::new(CONFIG_NAME));
+        let settings = layered((&user, user_path), Some((&install, install_path))).unwrap();
+
+        let source = |key: &str| {
+            let (value, path) = &settings[key];
+            (value.to_string(), path.to_string_lossy().to_string())
+        };
+        let from = |value: &str, path: &Path| (value.to_string(), path.display().to_string());
+        assert_eq!(source("runner"), from("\"GE-Proton9-20\"", install_path));
+        assert_eq!(source("launch_args"), from("\"--debug\"", user_path));
+        assert_eq!(source("env.DXVK_HUD"), from("\"0\"", install_path));
+        assert_eq!(source("env.MANGOHUD"), from("\"1\"", user_path));
+        assert_eq!(source("app_id"), from("39140", install_path));
+
+        // Without an install only the user's defaults apply
+        let settings = layered((&user, user_path), None).unwrap();
+        assert_eq!(
+            settings.keys().collect::<Vec<_>>(),
+            ["env.DXVK_HUD", "env.MANGOHUD", "launch_args", "runner"]
+        );
+    }
+
+    const INSTALL: &str = "# Set up by hand\n\
+                           schema_version = 2\n\
+                           type = \"steam\" # the 2013 release\n\
+                           loader = \"7th_heaven\"\n\
+                           app_id = 39140\n\
+                           steam_dir = \"/steam\"\n\
+                           \n\
+                           [env]\n\
+                           # Shown in game\n\
+                           DXVK_HUD = \"fps\"\n";
+
+    fn config_file() -> (tempfile::TempDir, ConfigFile) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(CONFIG_NAME), INSTALL).unwrap();
+        let file = ConfigFile::open(dir.path()).unwrap();
+        (dir, file)
+    }
+
+    #[test]
+    fn config_file_edits_keep_the_rest_of_the_file() {
+        let (dir, mut file) = config_file();
+        file.set("type", "gog").unwrap();
+        file.set("runner", "proton_9").unwrap();
+        file.env_set("DXVK_HUD", "0").unwrap();
+        file.env_set("MANGOHUD", "1").unwrap();
+        file.save().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(CONFIG_NAME)).unwrap(),
+            "# Set up by hand\n\
+             schema_version = 2\n\
+             type = \"gog\" # the 2013 release\n\
+             loader = \"7th_heaven\"\n\
+             app_id = 39140\n\
+             steam_dir = \"/steam\"\n\
+             runner = \"proton_9\"\n\
+             \n\
+             [env]\n\
+             # Shown in game\n\
+             DXVK_HUD = \"0\"\n\
+             MANGOHUD = \"1\"\n"
+        );
+
+        assert!(file.unset("runner").unwrap());
+        assert!(!file.unset("runner").unwrap());
+        assert!(file.env_unset("MANGOHUD"));
+        assert!(!file.env_unset("MANGOHUD"));
+        file.set("type", "steam").unwrap();
+        file.env_set("DXVK_HUD", "fps").unwrap();
+        file.save().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(CONFIG_NAME)).unwrap(),
+            INSTALL
+        );
+    }
+
+    #[test]
+    fn config_file_rejects_bad_values() {
+        let (_dir, mut file) = config_file();
+        assert!(file.set("schema_version", "3").is_err());
+        assert!(file.unset("schema_version").is_err());
+        assert!(file.set("env", "DXVK_HUD=0").is_err());
+        assert!(file.set("app_id", "abc").is_err());
+        assert!(file.set("channel", "nightly").is_err());
+        assert_eq!(file.config().unwrap().app_id, 39140);
+
+        file.set("app_id", "3837340").unwrap();
+        assert_eq!(file.config().unwrap().app_id, 3837340);
+    }
+
+    #[test]
+    fn config_file_rejects_unknown_keys() {
+        let (dir, mut file) = config_file();
+        file.set("colour", "red").unwrap();
+        let err = file.save().unwrap_err();
+        assert!(format!("{err:#}").contains("unknown key `colour`"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(CONFIG_NAME)).unwrap(),
+            INSTALL
+        );
+
+        file.set("type", "epic").unwrap();
+        assert!(file.unset("colour").unwrap());
+        assert!(file.save().is_err());
+    }
 }
@@ -0,0 +1,325 @@
+use crate::config_handler::{self, Config, ConfigFile, InstallType};
+use crate::gamelib_helper::{self, steam_game, steam_lib, steam_proton, PrefixedGame};
+use crate::mod_loader::{self, ModLoader};
+use crate::settings_xml::Settings;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// An install being diagnosed
+pub struct Install {
+    pub path: PathBuf,
+    pub config: Config,
+    pub loader: Box<dyn ModLoader>,
+}
+
+impl Install {
+    pub fn load(path: &Path) -> Result<Self> {
+        let config = config_handler::load(path)?;
+        let loader = mod_loader::from_id(&config.loader)
+            .with_context(|| format!("Unknown loader '{}' in config", config.loader))?;
+        Ok(Install {
+            path: path.to_path_buf(),
+            config,
+            loader,
+        })
+    }
+
+    fn game(&self) -> Result<Box<dyn PrefixedGame>> {
+        gamelib_helper::from_config(
+            &self.config.clone().with_defaults(config_handler::user()),
+            self.loader.as_ref(),
+        )
+    }
+
+    fn settings_path(&self) -> PathBuf {
+        mod_loader::settings_path(self.loader.as_ref(), &self.path)
+    }
+
+    /// Change a setting in the install's config and reload it
+    fn set_config(&mut self, key: &str, value: Option<&str>) -> Result<()> {
+        let mut file = ConfigFile::open(&self.path)?;
+        match value {
+            Some(value) => file.set(key, value)?,
+            None => {
+                file.unset(key)?;
+            }
+        }
+        file.save()?;
+        self.config = config_handler::load(&self.path)?;
+        Ok(())
+    }
+}
+
+pub enum Status {
+    Pass,
+    /// The check couldn't run because something it depends on is broken
+    Skipped(String),
+    Fail {
+        problem: String,
+        hint: String,
+    },
+}
+
+/// A named check of one part of an install, with a repair for the problems it can fix
+pub struct Check {
+    pub name: &'static str,
+    check: fn(&Install) -> Result<Status>,
+    fix: Option<fn(&mut Install) -> Result<()>>,
+}
+
+impl Check {
+    pub fn run(&self, install: &Install) -> Status {
+        (self.check)(install).unwrap_or_else(|e| Status::Fail {
+            problem: format!("{e:#}"),
+            hint: "See MateriaForge.log for details".to_string(),
+        })
+    }
+
+    pub fn can_fix(&self) -> bool {
+        self.fix.is_some()
+    }
+
+    pub fn fix(&self, install: &mut Install) -> Result<()> {
+        match self.fix {
+            Some(fix) => fix(install),
+            None => bail!("{} can't be repaired automatically", self.name),
+        }
+    }
+}
+
+/// Every check, in the order they should run; later checks rely on earlier ones passing
+pub const CHECKS: &[Check] = &[
+    Check {
+        name: "loader",
+        check: check_loader,
+        fix: None,
+    },
+    Check {
+        name: "steam_dir",
+        check: check_steam_dir,
+        fix: Some(fix_steam_dir),
+    },
+    Check {
+        name: "runner",
+        check: check_runner,
+        fix: Some(fix_runner),
+    },
+    Check {
+        name: "game",
+        check: check_game,
+        fix: None,
+    },
+    Check {
+        name: "prefix",
+        check: check_prefix,
+        fix: None,
+    },
+    Check {
+        name: "timeout.exe",
+        check: check_timeout_exe,
+        fix: Some(fix_timeout_exe),
+    },
+    Check {
+        name: "settings.xml",
+        check: check_settings_exe,
+        fix: Some(fix_settings_exe),
+    },
+    Check {
+        name: "dxvk.conf",
+        check: check_dxvk_conf,
+        fix: Some(fix_dxvk_conf),
+    },
+];
+
+fn fail(problem: impl Into<String>, hint: impl Into<String>) -> Result<Status> {
+    Ok(Status::Fail {
+        problem: problem.into(),
+        hint: hint.into(),
+    })
+}
+
+fn check_loader(install: &Install) -> Result<Status> {
+    let exe = install.path.join(install.loader.exe_name());
+    if !exe.is_file() {
+        return fail(
+            format!("{} is missing", exe.display()),
+            "Run `MateriaForge update` to reinstall the mod loader",
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn check_steam_dir(install: &Install) -> Result<Status> {
+    if install.config.install_type != InstallType::Steam {
+        return Ok(Status::Skipped("not a Steam install".to_string()));
+    }
+    let Some(steam_dir) = &install.config.steam_dir else {
+        return fail("`steam_dir` isn't set", "Run with --fix to look for Steam");
+    };
+    if let Err(e) = steamlocate::SteamDir::from_dir(steam_dir) {
+        return fail(
+            format!("{} isn't a Steam installation: {e}", steam_dir.display()),
+            "Run with --fix to look for Steam again",
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn fix_steam_dir(install: &mut Install) -> Result<()> {
+    let steam_dir = steam_lib::get_library()?;
+    steam_game::get_game(install.config.app_id, steam_dir.clone()).with_context(|| {
+        format!(
+            "Found Steam in {}, but not the game",
+            steam_dir.path().display()
+        )
+    })?;
+    install.set_config("steam_dir", Some(&steam_dir.path().to_string_lossy()))
+}
+
+fn check_runner(install: &Install) -> Result<Status> {
+    let (InstallType::Steam, Some(runner), Some(steam_dir)) = (
+        install.config.install_type,
+        &install.config.runner,
+        &install.config.steam_dir,
+    ) else {
+        return Ok(Status::Skipped("no Proton version configured".to_string()));
+    };
+    let Ok(steam_dir) = steamlocate::SteamDir::from_dir(steam_dir) else {
+        return Ok(Status::Skipped("Steam not found".to_string()));
+    };
+    let versions = steam_proton::find_all_versions(steam_dir)?;
+    if !versions.iter().any(|version| version.name == *runner) {
+        return fail(
+            format!("The configured Proton version '{runner}' isn't installed"),
+            "Install it from Steam, or run with --fix to use the game's Proton version instead",
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn fix_runner(install: &mut Install) -> Result<()> {
+    install.set_config("runner", None)
+}
+
+fn check_game(install: &Install) -> Result<Status> {
+    let game = match install.game() {
+        Ok(game) => game,
+        Err(e) => {
+            let hint =
+                "Make sure the game is still installed, then check the paths in MateriaForge.toml";
+            return fail(format!("{e:#}"), hint);
+        }
+    };
+    let exe = game.path().join(&install.config.edition()?.exe);
+    if !exe.is_file() {
+        return fail(
+            format!("{} is missing", exe.display()),
+            "Verify the game's files in its store",
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn check_prefix(install: &Install) -> Result<Status> {
+    let Ok(game) = install.game() else {
+        return Ok(Status::Skipped("game not found".to_string()));
+    };
+    let drive_c = game.prefix().join("drive_c");
+    if !drive_c.is_dir() {
+        let hint = match install.config.install_type {
+            InstallType::Steam => "Start the game once from Steam so it creates a new prefix, then run `MateriaForge doctor --fix`",
+            _ => "Start the game once from its launcher so it creates a new prefix, then run `MateriaForge doctor --fix`",
+        };
+        return fail(
+            format!("The game's prefix {} is gone", game.prefix().display()),
+            hint,
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn timeout_exe_path(game: &dyn PrefixedGame) -> PathBuf {
+    game.prefix().join("drive_c/windows/system32/timeout.exe")
+}
+
+fn check_timeout_exe(install: &Install) -> Result<Status> {
+    let Ok(game) = install.game() else {
+        return Ok(Status::Skipped("game not found".to_string()));
+    };
+    if !game.prefix().join("drive_c").is_dir() {
+        return Ok(Status::Skipped("prefix missing".to_string()));
+    }
+    let path = timeout_exe_path(game.as_ref());
+    if !path.is_file() {
+        return fail(
+            format!("{} is missing", path.display()),
+            "Run with --fix to copy it into the prefix",
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn fix_timeout_exe(install: &mut Install) -> Result<()> {
+    mod_loader::patch_prefix(install.game()?.as_ref())
+}
+
+fn check_settings_exe(install: &Install) -> Result<Status> {
+    let path = install.settings_path();
+    if !path.is_file() {
+        return fail(
+            format!("{} is missing", path.display()),
+            "Run with --fix to write a new one",
+        );
+    }
+    let Ok(game) = install.game() else {
+        return Ok(Status::Skipped("game not found".to_string()));
+    };
+    let expected = install.config.edition()?.exe_path(game.path());
+    let element = install.loader.exe_setting();
+    let found = Settings::load(&path)?.get(element)?;
+    if found != expected {
+        return fail(
+            format!("{element} points to {found}, but the game is at {expected}"),
+            "Run with --fix to point it at the game",
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn fix_settings_exe(install: &mut Install) -> Result<()> {
+    let path = install.settings_path();
+    let game = install.game()?;
+    let edition = install.config.edition()?;
+    if !path.is_file() {
+        let channel = install
+            .config
+            .channel
+            .or(config_handler::user().channel)
+            .unwrap_or_default();
+        return mod_loader::write_settings(
+            install.loader.as_ref(),
+            &edition,
+            game.as_ref(),
+            &install.path,
+            channel.update_channel(),
+        );
+    }
+    let mut settings = Settings::load(&path)?;
+    settings.set(install.loader.exe_setting(), &edition.exe_path(game.path()))?;
+    settings.save()
+}
+
+fn check_dxvk_conf(install: &Install) -> Result<Status> {
+    let path = install.path.join("dxvk.conf");
+    if !path.is_file() {
+        return fail(
+            format!("{} is missing", path.display()),
+            "Run with --fix to restore it",
+        );
+    }
+    Ok(Status::Pass)
+}
+
+fn fix_dxvk_conf(install: &mut Install) -> Result<()> {
+    mod_loader::write_dxvk_conf(&install.path)
+}
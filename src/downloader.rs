@@ -0,0 +1,356 @@
+use crate::config_handler::Channel;
+use anyhow::{bail, Context, Result};
+use indicatif::ProgressBar;
+use regex::Regex;
+use reqwest::{blocking::Client, header::RANGE, StatusCode};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+const MAX_ATTEMPTS: u32 = 4;
+/// Next to a cached download, the channel of the release it came from
+const CHANNEL_FILE: &str = ".channel";
+
+/// A GitHub release asset
+#[derive(Debug, Clone)]
+pub struct Asset {
+    pub name: String,
+    pub url: String,
+    /// Size in bytes, 0 if GitHub didn't report one
+    pub size: u64,
+    /// e.g. `sha256:<hex>`; only set on assets uploaded after GitHub started hashing them
+    pub digest: Option<String>,
+}
+
+impl Asset {
+    /// Read an entry of a release's `assets` array
+    pub fn from_json(asset: &serde_json::Value) -> Result<Self> {
+        Ok(Asset {
+            name: asset["name"]
+                .as_str()
+                .context("Asset has no name")?
+                .to_string(),
+            url: asset["browser_download_url"]
+                .as_str()
+                .context("No download URL was passed")?
+                .to_string(),
+            size: asset["size"].as_u64().unwrap_or(0),
+            digest: asset["digest"].as_str().map(String::from),
+        })
+    }
+}
+
+/// Download `asset` into `destination`, returning the path of the verified file.
+///
+/// Data goes to a `.part` file first, so an interrupted download is resumed on the next
+/// attempt and a truncated or corrupt file is never left under the asset's name.
+pub fn download(
+    client: &Client,
+    asset: &Asset,
+    destination: &Path,
+    pb: &ProgressBar,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(destination)?;
+    let file_path = destination.join(&asset.name);
+    let part_path = destination.join(format!("{}.part", asset.name));
+
+    if file_path.is_file() && verify(asset, &file_path).is_ok() {
+        log::info!("Reusing downloaded {}", file_path.display());
+        return Ok(file_path);
+    }
+
+    let mut attempt = 1;
+    loop {
+        match fetch(client, asset, &part_path, pb).and_then(|_| verify(asset, &part_path)) {
+            Ok(()) => break,
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                let delay = Duration::from_secs(2u64.pow(attempt - 1));
+                log::warn!(
+                    "Download of {} failed (attempt {attempt}/{MAX_ATTEMPTS}), retrying in {}s: {e:#}",
+                    asset.name,
+                    delay.as_secs()
+                );
+                pb.set_message(format!("Retrying download of {}", asset.name));
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(e.context(format!(
+                    "Couldn't download {} after {MAX_ATTEMPTS} attempts",
+                    asset.name
+                )))
+            }
+        }
+    }
+
+    std::fs::rename(&part_path, &file_path)
+        .with_context(|| format!("Couldn't move download to {}", file_path.display()))?;
+    Ok(file_path)
+}
+
+/// Guess a release version from an installer's file name, e.g. `7thHeaven-v4.3.0.exe`
+pub fn version_from_file_name(name: &str) -> Option<String> {
+    let re = Regex::new(r"v?\d+(\.\d+)+").expect("Invalid version regex");
+    re.find(name).map(|m| m.as_str().to_string())
+}
+
+/// Record that the download in `release_dir` is from a release on `channel`
+pub fn set_cached_channel(release_dir: &Path, channel: Channel) -> Result<()> {
+    let path = release_dir.join(CHANNEL_FILE);
+    std::fs::write(&path, channel.as_str())
+        .with_context(|| format!("Couldn't write {}", path.display()))
+}
+
+/// The most recently downloaded asset in `cache_dir` accepted by `matches`, with the release
+/// tag it was downloaded for. `tag` limits the search to one release; otherwise only releases
+/// `channel` would pick are considered, i.e. stable releases unless it's canary.
+///
+/// Downloads are cached as `<cache_dir>/<tag>/<asset name>`.
+pub fn latest_cached(
+    cache_dir: &Path,
+    tag: Option<&str>,
+    channel: Channel,
+    matches: impl Fn(&str) -> bool,
+) -> Option<(PathBuf, String)> {
+    std::fs::read_dir(cache_dir)
+        .ok()?
+        .flatten()
+        .filter_map(|release_dir| {
+            let dir_tag = release_dir.file_name().to_string_lossy().to_string();
+            match tag {
+                Some(tag) if tag != dir_tag => return None,
+                Some(_) => {}
+                None if channel == Channel::Canary => {}
+                // Downloads from before the channel was recorded could be pre-releases
+                None => {
+                    let cached = std::fs::read_to_string(release_dir.path().join(CHANNEL_FILE));
+                    if cached.ok()?.trim().parse::<Channel>().ok()? != Channel::Stable {
+                        return None;
+                    }
+                }
+            }
+            let tag = dir_tag;
+            std::fs::read_dir(release_dir.path())
+                .ok()?
+                .flatten()
+                .find(|file| matches(&file.file_name().to_string_lossy()))
+                .and_then(|file| Some((file.metadata().ok()?.modified().ok()?, file.path(), tag)))
+        })
+        .max_by_key(|(modified, _, _)| *modified)
+        .map(|(_, path, tag)| (path, tag))
+}
+
+/// Fetch whatever is missing from the `.part` file, resuming with a Range request
+fn fetch(client: &Client, asset: &Asset, part_path: &Path, pb: &ProgressBar) -> Result<()> {
+    let mut existing = part_path.metadata().map(|m| m.len()).unwrap_or(0);
+    if asset.size > 0 && existing > asset.size {
+        log::warn!("Discarding oversized partial download of {}", asset.name);
+        std::fs::remove_file(part_path)?;
+        existing = 0;
+    }
+    if asset.size > 0 && existing == asset.size {
+        pb.set_position(existing);
+        return Ok(());
+    }
+
+    let mut request = client.get(&asset.url);
+    if existing > 0 {
+        log::info!("Resuming download of {} at byte {existing}", asset.name);
+        request = request.header(RANGE, format!("bytes={existing}-"));
+    }
+    let mut response = request.send()?;
+
+    let mut file = match response.status() {
+        StatusCode::PARTIAL_CONTENT => OpenOptions::new().append(true).open(part_path)?,
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            std::fs::remove_file(part_path)?;
+            bail!("Server rejected resuming the download, starting over");
+        }
+        status if status.is_success() => {
+            // Server ignored the Range header and sent the whole file
+            existing = 0;
+            File::create(part_path)?
+        }
+        status => bail!("Download failed with HTTP {status}"),
+    };
+
+    pb.set_position(existing);
+    let mut writer = pb.wrap_write(&mut file);
+    response.copy_to(&mut writer)?;
+    Ok(())
+}
+
+/// Check a download against the asset's size and digest, discarding it on a digest mismatch
+fn verify(asset: &Asset, path: &Path) -> Result<()> {
+    let len = path.metadata()?.len();
+    if asset.size > 0 && len != asset.size {
+        bail!(
+            "Downloaded {len} of {} bytes for {}",
+            asset.size,
+            asset.name
+        );
+    }
+
+    let Some(digest) = &asset.digest else {
+        log::warn!("No digest published for {}, skipping checksum", asset.name);
+        return Ok(());
+    };
+    let Some(expected) = digest.strip_prefix("sha256:") else {
+        log::warn!(
+            "Unsupported digest '{digest}' for {}, skipping checksum",
+            asset.name
+        );
+        return Ok(());
+    };
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    let actual = hex::encode(hasher.finalize());
+    if !actual.eq_ignore_ascii_case(expected) {
+        std::fs::remove_file(path)?;
+        bail!(
+            "Checksum mismatch for {}: expected {expected}, got {actual}",
+            asset.name
+        );
+    }
+    log::info!("Verified SHA-256 of {}", asset.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, Server};
+
+    const INSTALLER: &[u8] = b"MZ 7th Heaven installer, version 4.3.0";
+
+    fn sha256(data: &[u8]) -> String {
+        format!("sha256:{}", hex::encode(Sha256::digest(data)))
+    }
+
+    fn asset(url: &str) -> Asset {
+        Asset {
+            name: "7thHeaven-v4.3.0.exe".to_string(),
+            url: format!("{url}/7thHeaven-v4.3.0.exe"),
+            size: INSTALLER.len() as u64,
+            digest: Some(sha256(INSTALLER)),
+        }
+    }
+
+    /// Serves [`INSTALLER`], honouring `Range: bytes=<start>-` when `ranges` is set
+    fn installer_server(ranges: bool) -> Server {
+        Server::start(move |request| {
+            let start = request
+                .header("range")
+                .filter(|_| ranges)
+                .and_then(|range| {
+                    range
+                        .strip_prefix("bytes=")?
+                        .strip_suffix('-')?
+                        .parse()
+                        .ok()
+                });
+            match start {
+                Some(start) => Response {
+                    status: 206,
+                    headers: vec![(
+                        "Content-Range",
+                        format!("bytes {start}-{}/{}", INSTALLER.len() - 1, INSTALLER.len()),
+                    )],
+                    body: INSTALLER[start..].to_vec(),
+                },
+                None => Response::ok(INSTALLER),
+            }
+        })
+    }
+
+    #[test]
+    fn resumes_part_file_at_its_length() {
+        let server = installer_server(true);
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("7thHeaven-v4.3.0.exe.part");
+        std::fs::write(&part, &INSTALLER[..10]).unwrap();
+
+        let path = download(
+            &Client::new(),
+            &asset(&server.url),
+            dir.path(),
+            &ProgressBar::hidden(),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), INSTALLER);
+        assert!(!part.exists());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].header("range"), Some("bytes=10-"));
+    }
+
+    #[test]
+    fn restarts_when_range_is_ignored() {
+        let server = installer_server(false);
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("7thHeaven-v4.3.0.exe.part");
+        std::fs::write(&part, &INSTALLER[..10]).unwrap();
+
+        fetch(
+            &Client::new(),
+            &asset(&server.url),
+            &part,
+            &ProgressBar::hidden(),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(&part).unwrap(), INSTALLER);
+    }
+
+    #[test]
+    fn complete_part_file_is_not_fetched_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("7thHeaven-v4.3.0.exe.part");
+        std::fs::write(&part, INSTALLER).unwrap();
+
+        // Nothing listens here, so any request would fail
+        fetch(
+            &Client::new(),
+            &asset("http://127.0.0.1:9"),
+            &part,
+            &ProgressBar::hidden(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn checksum_mismatch_discards_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("7thHeaven-v4.3.0.exe");
+        std::fs::write(&path, INSTALLER).unwrap();
+        let mut asset = asset("https://example.org");
+        verify(&asset, &path).unwrap();
+
+        asset.digest = Some(sha256(b"something else"));
+        let error = verify(&asset, &path).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Checksum mismatch for 7thHeaven-v4.3.0.exe"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn short_download_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("7thHeaven-v4.3.0.exe");
+        std::fs::write(&path, &INSTALLER[..10]).unwrap();
+        let error = verify(&asset("https://example.org"), &path).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Downloaded 10 of {} bytes for 7thHeaven-v4.3.0.exe",
+                INSTALLER.len()
+            )
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use crate::mod_loader::{s_drive_path, z_drive_path};
+use crate::resource_handler;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path, sync::OnceLock};
+
+static EDITIONS: OnceLock<Vec<Edition>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Store {
+    Steam,
+    Gog,
+}
+
+/// How the mod loader sees the game's files from inside the prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Drive {
+    /// Proton's S: drive, which maps the game's Steam library
+    Steam,
+    /// Wine's Z: drive, which maps the Linux root
+    Z,
+}
+
+/// One release of a game, as described in `resources/editions.toml`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Edition {
+    #[serde(skip)]
+    pub name: String,
+    pub loader: String,
+    pub store: Store,
+    pub app_id: u32,
+    pub edition: String,
+    pub exe: String,
+    pub installed_version: String,
+    pub suffix: String,
+    pub drive: Drive,
+}
+
+impl Edition {
+    /// Path to the game's exe as the mod loader sees it, given the game directory
+    pub fn exe_path(&self, game_path: &Path) -> String {
+        let exe = game_path.join(&self.exe);
+        match self.drive {
+            Drive::Z => z_drive_path(&exe),
+            Drive::Steam => s_drive_path(&exe),
+        }
+    }
+}
+
+fn parse(contents: &str, source: &str) -> Result<BTreeMap<String, Edition>> {
+    let mut editions: BTreeMap<String, Edition> = toml::from_str(contents)
+        .with_context(|| format!("Couldn't parse editions from {source}"))?;
+    for (name, edition) in editions.iter_mut() {
+        edition.name = name.clone();
+    }
+    Ok(editions)
+}
+
+fn load() -> Result<Vec<Edition>> {
+    let user_file = xdg::BaseDirectories::with_prefix("MateriaForge")
+        .get_config_file("editions.toml")
+        .filter(|path| path.is_file());
+    load_with(user_file.as_deref())
+}
+
+/// The built-in editions, with the tables of `user_file` replacing or adding to them
+fn load_with(user_file: Option<&Path>) -> Result<Vec<Edition>> {
+    let mut editions = parse(resource_handler::EDITIONS_TOML, "built-in editions.toml")?;
+
+    if let Some(user_file) = user_file {
+        log::info!("Loading edition overrides from {}", user_file.display());
+        let contents = std::fs::read_to_string(user_file)
+            .with_context(|| format!("Couldn't read {}", user_file.display()))?;
+        editions.extend(parse(&contents, &user_file.display().to_string())?);
+    }
+
+    Ok(editions.into_values().collect())
+}
+
+/// Every known edition, built-in ones merged with the user's overrides
+pub fn all() -> Result<&'static [Edition]> {
+    if let Some(editions) = EDITIONS.get() {
+        return Ok(editions);
+    }
+    let editions = load()?;
+    Ok(EDITIONS.get_or_init(|| editions))
+}
+
+pub fn by_app_id(app_id: u32) -> Result<&'static Edition> {
+    all()?
+        .iter()
+        .find(|edition| edition.app_id == app_id)
+        .with_context(|| format!("No edition with app ID {app_id} in editions.toml"))
+}
+
+/// Editions a loader supports from one store, in app ID order
+pub fn for_loader(loader_id: &str, store: Store) -> Result<Vec<&'static Edition>> {
+    let mut editions: Vec<_> = all()?
+        .iter()
+        .filter(|edition| edition.loader == loader_id && edition.store == store)
+        .collect();
+    editions.sort_by_key(|edition| edition.app_id);
+    Ok(editions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_editions_parse() {
+        let editions = load_with(None).unwrap();
+        let ff7_2013 = editions.iter().find(|e| e.name == "ff7_2013").unwrap();
+        assert_eq!(ff7_2013.app_id, 39140);
+        assert_eq!(ff7_2013.store, Store::Steam);
+        assert_eq!(ff7_2013.drive, Drive::Steam);
+        assert!(editions.iter().any(|e| e.store == Store::Gog));
+
+        // App IDs identify editions, so none may repeat
+        let mut app_ids: Vec<_> = editions.iter().map(|e| e.app_id).collect();
+        app_ids.sort();
+        app_ids.dedup();
+        assert_eq!(app_ids.len(), editions.len());
+    }
+
+    #[test]
+    fn user_editions_replace_and_add_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_file = dir.path().join("editions.toml");
+        std::fs::write(
+            &user_file,
+            "[ff7_2013]\n\
+             loader = \"7th_heaven\"\n\
+             store = \"steam\"\n\
+             app_id = 39140\n\
+             edition = \"2013\"\n\
+             exe = \"ff7_de.exe\"\n\
+             installed_version = \"Steam\"\n\
+             suffix = \"(2013, DE)\"\n\
+             drive = \"z\"\n\
+             \n\
+             [ff8_remastered_gog]\n\
+             loader = \"junction_viii\"\n\
+             store = \"gog\"\n\
+             app_id = 1208380010\n\
+             edition = \"gog_remastered\"\n\
+             exe = \"FFVIII.exe\"\n\
+             installed_version = \"Steam\"\n\
+             suffix = \"(Remastered, GOG)\"\n\
+             drive = \"z\"\n",
+        )
+        .unwrap();
+
+        let built_in = load_with(None).unwrap();
+        let editions = load_with(Some(&user_file)).unwrap();
+        assert_eq!(editions.len(), built_in.len() + 1);
+
+        let ff7_2013 = editions.iter().find(|e| e.name == "ff7_2013").unwrap();
+        assert_eq!(ff7_2013.exe, "ff7_de.exe");
+        assert_eq!(ff7_2013.suffix, "(2013, DE)");
+        assert_eq!(ff7_2013.drive, Drive::Z);
+        let ff8 = editions
+            .iter()
+            .find(|e| e.name == "ff8_remastered_gog")
+            .unwrap();
+        assert_eq!(ff8.loader, "junction_viii");
+
+        // Tables the user didn't touch are the built-in ones
+        for edition in built_in.iter().filter(|e| e.name != "ff7_2013") {
+            let merged = editions.iter().find(|e| e.name == edition.name).unwrap();
+            assert_eq!(merged.exe, edition.exe);
+        }
+    }
+
+    #[test]
+    fn user_editions_need_every_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_file = dir.path().join("editions.toml");
+        std::fs::write(&user_file, "[ff7_2013]\nexe = \"ff7_de.exe\"\n").unwrap();
+        assert!(load_with(Some(&user_file)).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::gamelib_helper::steam_lib::SteamUser;
+use crate::manifest::{self, Change, FileKind};
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+const EXTENSIONS: [&str; 2] = ["png", "jpg"];
+
+/// The images Steam shows for a game in its library, Big Picture and Game Mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Portrait capsule in the library grid
+    Grid,
+    /// Landscape capsule, e.g. under "Recent games"
+    WideGrid,
+    /// Banner at the top of the game's page
+    Hero,
+    /// Title drawn over the hero
+    Logo,
+    Icon,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 5] = [
+        Kind::Grid,
+        Kind::WideGrid,
+        Kind::Hero,
+        Kind::Logo,
+        Kind::Icon,
+    ];
+
+    /// Name of the image for this kind in a custom artwork folder, without the extension
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Grid => "grid",
+            Kind::WideGrid => "wide_grid",
+            Kind::Hero => "hero",
+            Kind::Logo => "logo",
+            Kind::Icon => "icon",
+        }
+    }
+
+    /// Name Steam looks for in `userdata/<id>/config/grid/`, without the extension
+    fn file_stem(self, app_id: u32) -> String {
+        match self {
+            Kind::Grid => format!("{app_id}p"),
+            Kind::WideGrid => app_id.to_string(),
+            Kind::Hero => format!("{app_id}_hero"),
+            Kind::Logo => format!("{app_id}_logo"),
+            Kind::Icon => format!("{app_id}_icon"),
+        }
+    }
+}
+
+/// The image for `kind` in the custom artwork folder `dir`, e.g. `dir/hero.jpg`
+pub fn custom(dir: &Path, kind: Kind) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{}.{extension}", kind.name())))
+        .find(|path| path.is_file())
+}
+
+/// Install artwork for the shortcut `app_id` for `users`. Images in `custom_dir` replace
+/// whatever is there, moving the user's own aside until uninstall; kinds without one fall back
+/// to `default(kind)`, which doesn't replace artwork the user already set in Steam.
+pub fn install(
+    steam_dir: &Path,
+    users: &[SteamUser],
+    app_id: u32,
+    default: impl Fn(Kind) -> &'static [u8],
+    custom_dir: Option<&Path>,
+) -> Result<()> {
+    if let Some(dir) = custom_dir {
+        if !Kind::ALL.iter().any(|kind| custom(dir, *kind).is_some()) {
+            let names = Kind::ALL.map(Kind::name).join(", ");
+            bail!(
+                "No artwork in {}; expected any of {names} as .png or .jpg",
+                dir.display()
+            );
+        }
+    }
+
+    for user in users {
+        let grid = user.config_dir(steam_dir).join("grid");
+        std::fs::create_dir_all(&grid)
+            .with_context(|| format!("Couldn't create {}", grid.display()))?;
+
+        for kind in Kind::ALL {
+            let stem = kind.file_stem(app_id);
+            let existing: Vec<PathBuf> = EXTENSIONS
+                .iter()
+                .map(|extension| grid.join(format!("{stem}.{extension}")))
+                .filter(|path| path.is_file())
+                .collect();
+            match custom_dir.and_then(|dir| custom(dir, kind)) {
+                Some(image) => {
+                    let extension = image.extension().unwrap_or_default().to_string_lossy();
+                    let contents = std::fs::read(&image)
+                        .with_context(|| format!("Couldn't read {}", image.display()))?;
+                    // Steam would also keep showing an image of the other type
+                    for path in existing {
+                        move_aside(path)?;
+                    }
+                    manifest::write(
+                        &grid.join(format!("{stem}.{extension}")),
+                        &contents,
+                        FileKind::Resource,
+                    )?;
+                }
+                None if existing.is_empty() => {
+                    manifest::write(
+                        &grid.join(format!("{stem}.png")),
+                        default(kind),
+                        FileKind::Resource,
+                    )?;
+                }
+                None => {}
+            }
+        }
+        log::info!("Installed artwork for {app_id} in {}", grid.display());
+    }
+    Ok(())
+}
+
+/// Move the user's image at `path` aside so uninstall can put it back. When an earlier install
+/// already moved theirs aside, `path` is MateriaForge's own and is just removed.
+fn move_aside(path: PathBuf) -> Result<()> {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let backup = path.with_extension(format!("{extension}.materiaforge-bak"));
+    if backup.exists() {
+        return std::fs::remove_file(&path)
+            .with_context(|| format!("Couldn't remove {}", path.display()));
+    }
+    std::fs::rename(&path, &backup)
+        .with_context(|| format!("Couldn't move {} aside", path.display()))?;
+    manifest::record(Change::Backup { path, backup });
+    Ok(())
+}
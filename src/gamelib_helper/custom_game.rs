@@ -0,0 +1,154 @@
+use crate::editions::Edition;
+use crate::gamelib_helper::{run_in_wine_prefix, Game, PrefixRunner, Runner};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A game in a wine prefix the user pointed MateriaForge at, e.g. from a GOG offline
+/// installer or Bottles
+#[derive(Debug, Clone)]
+pub struct CustomGame {
+    pub app_id: u32,
+    pub name: String,
+    pub path: PathBuf,
+    pub prefix: PathBuf,
+    pub runner: Option<Runner>,
+}
+
+impl Game for CustomGame {
+    fn app_id(&self) -> u32 {
+        self.app_id
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn path(&self) -> &Path {
+        &self.path
+    }
+    fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+    fn runner(&self) -> Option<&Runner> {
+        self.runner.as_ref()
+    }
+}
+
+impl PrefixRunner for CustomGame {
+    fn run_in_prefix(
+        &self,
+        exe_to_launch: PathBuf,
+        args: Option<Vec<String>>,
+        env: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let wine = self
+            .runner
+            .as_ref()
+            .with_context(|| format!("Couldn't find runner for game: {self:#?}"))?;
+        run_in_wine_prefix(exe_to_launch, &self.prefix, wine, args, env)
+    }
+}
+
+/// Build a [`CustomGame`] from user-supplied paths, checking that each one exists.
+///
+/// `runner` is either a wine binary or a GE-Proton `proton` script. Proton keeps its prefix in
+/// `pfx/` of the directory it's given, so with a `proton` script `prefix` is that directory (or
+/// the `pfx` directory in it).
+pub fn get_game(
+    edition: &Edition,
+    game_dir: &Path,
+    prefix: &Path,
+    runner: &Path,
+) -> Result<CustomGame> {
+    let exe = game_dir.join(&edition.exe);
+    if !exe.is_file() {
+        bail!(
+            "Couldn't find {} in game directory '{}'",
+            edition.exe,
+            game_dir.display()
+        );
+    }
+    let runner = get_runner(runner)?;
+    let prefix = match runner.name.as_str() {
+        "proton" => proton_prefix(prefix)?,
+        _ => prefix.to_path_buf(),
+    };
+    if !prefix.join("drive_c").is_dir() {
+        bail!(
+            "'{}' doesn't look like a wine prefix (no drive_c directory)",
+            prefix.display()
+        );
+    }
+
+    Ok(CustomGame {
+        app_id: edition.app_id,
+        name: edition.name.clone(),
+        path: game_dir.to_path_buf(),
+        prefix,
+        runner: Some(runner),
+    })
+}
+
+/// The wine prefix Proton uses when given `prefix`, which must be laid out like Steam's
+/// `compatdata/<appid>` (`<dir>/pfx/drive_c`) for it to use the prefix that's patched
+fn proton_prefix(prefix: &Path) -> Result<PathBuf> {
+    if prefix.join("pfx/drive_c").is_dir() {
+        return Ok(prefix.join("pfx"));
+    }
+    if prefix.file_name().is_some_and(|name| name == "pfx") {
+        return Ok(prefix.to_path_buf());
+    }
+    bail!(
+        "Proton would set up a new prefix in '{}', not use this one. With a proton script, the \
+         prefix needs to be in pfx/ (pfx/drive_c)",
+        prefix.join("pfx").display()
+    );
+}
+
+/// Editions whose exe is present in `game_dir`, out of `candidates`
+pub fn matching_editions<'a>(candidates: &[&'a Edition], game_dir: &Path) -> Vec<&'a Edition> {
+    candidates
+        .iter()
+        .filter(|edition| game_dir.join(&edition.exe).is_file())
+        .copied()
+        .collect()
+}
+
+fn get_runner(path: &Path) -> Result<Runner> {
+    if !path.is_file() {
+        bail!("Runner '{}' doesn't exist", path.display());
+    }
+    let file_name = path
+        .file_name()
+        .context("Runner path has no file name")?
+        .to_string_lossy();
+
+    // Proton builds are named after their directory, e.g. GE-Proton9-20/proton
+    if file_name == "proton" {
+        let version = path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .context("Couldn't get Proton version from runner path")?
+            .to_string_lossy()
+            .to_string();
+        // Valve's Proton needs the Steam runtime, which a custom prefix doesn't have
+        if !version.contains("GE") {
+            bail!(
+                "'{}' doesn't seem to be GE-Proton; only GE-Proton can run outside Steam",
+                path.display()
+            );
+        }
+        return Ok(Runner {
+            name: "proton".to_string(),
+            pretty_name: version,
+            path: path.to_path_buf(),
+            runtime: None,
+        });
+    }
+
+    Ok(Runner {
+        name: "wine".to_string(),
+        pretty_name: path.display().to_string(),
+        path: path.to_path_buf(),
+        runtime: None,
+    })
+}
@@ -1,11 +1,8 @@
-use crate::config_handler;
-use crate::gamelib_helper::{spawn_wine_log_threads, Game, PrefixRunner, Runner, Runtime};
-use anyhow::{bail, Context, Result};
+use crate::gamelib_helper::{run_in_wine_prefix, Game, PrefixRunner, Runner, Runtime};
+use anyhow::{Context, Result};
 use serde_json::Value;
-use std::{
-    path::{Path, PathBuf},
-    process::{Command, Stdio},
-};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct GogGame {
@@ -35,8 +32,13 @@ impl Game for GogGame {
 }
 
 impl PrefixRunner for GogGame {
-    fn run_in_prefix(&self, exe_to_launch: PathBuf, args: Option<Vec<String>>) -> Result<()> {
-        run_in_prefix(exe_to_launch, self, args)
+    fn run_in_prefix(
+        &self,
+        exe_to_launch: PathBuf,
+        args: Option<Vec<String>>,
+        env: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        run_in_prefix(exe_to_launch, self, args, env)
     }
 }
 
@@ -44,81 +46,13 @@ pub fn run_in_prefix(
     exe_to_launch: PathBuf,
     game: &GogGame,
     args: Option<Vec<String>>,
+    env: &BTreeMap<String, String>,
 ) -> Result<()> {
     let wine = game
         .runner
         .clone()
         .with_context(|| format!("Couldn't find runner for game: {game:#?}"))?;
-    log::info!("Using runner: {}", wine.pretty_name);
-    log::info!("Runner bin: {}", wine.path.display());
-    log::info!("Wine prefix: {}", game.prefix.display());
-    log::info!("Using umu runtime: {}", wine.runtime.is_some());
-
-    let mut command = match (&wine.runtime, wine.name.as_str()) {
-        (Some(umu), _) => {
-            let mut cmd = Command::new(&umu.path);
-            cmd.env("WINEPREFIX", &game.prefix).env(
-                "PROTONPATH",
-                &wine
-                    .path
-                    .parent()
-                    .context("Failed to get parent of wine path")?,
-            );
-            cmd
-        }
-        (None, "wine") => {
-            let mut cmd = Command::new(&wine.path);
-            cmd.env("WINEPREFIX", &game.prefix);
-            cmd
-        }
-        (None, "proton") => {
-            let mut cmd = Command::new(&wine.path);
-            if !wine.pretty_name.contains("GE") {
-                bail!("Found proton runner, but it doesn't seem to be GE-Proton. Runner: {wine:?}");
-            }
-            cmd.env("STEAM_COMPAT_DATA_PATH", &game.prefix)
-                .env("SteamGameId", "0")
-                .arg("runinprefix");
-            cmd
-        }
-        (None, other) => bail!("Unknown runner type: {other}"),
-    };
-    command
-        .env("WINEDLLOVERRIDES", "dinput=n,b")
-        .envs(config_handler::read_env_vars())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .arg(&exe_to_launch);
-    let args = args.unwrap_or_default();
-    for arg in args {
-        log::info!("run_in_prefix arg: {arg}");
-        command.arg(arg);
-    }
-
-    let mut child = command.spawn()?;
-    log::info!(
-        "Launched {}",
-        exe_to_launch
-            .file_name()
-            .context("Couldn't get file_name")?
-            .to_string_lossy()
-    );
-
-    let stdout = child.stdout.take().context("Failed to capture stdout")?;
-    let stderr = child.stderr.take().context("Failed to capture stderr")?;
-
-    let (stdout_thread, stderr_thread) = spawn_wine_log_threads(stdout, stderr)?;
-
-    let status = child.wait()?;
-
-    stdout_thread.join().expect("Failed to join stdout thread");
-    stderr_thread.join().expect("Failed to join stderr thread");
-
-    if status.success() {
-        Ok(log::info!("Process exited successfully"))
-    } else {
-        bail!("Process exited with an error: {status}");
-    }
+    run_in_wine_prefix(exe_to_launch, &game.prefix, &wine, args, env)
 }
 
 pub fn get_game(app_id: u32, game: &lib_game_detector::data::Game) -> Result<GogGame> {
@@ -187,7 +121,7 @@ pub fn get_game(app_id: u32, game: &lib_game_detector::data::Game) -> Result<Gog
             .path_game_dir
             .clone()
             .context("Game is missing path_game_dir in detector result")?,
-        prefix: prefix,
+        prefix,
         runner: Some(runner),
     })
 }
@@ -0,0 +1,358 @@
+use crate::gamelib_helper::{run_in_wine_prefix, Game, PrefixRunner, Runner, Runtime};
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct LutrisGame {
+    pub app_id: u32,
+    pub name: String,
+    pub slug: String,
+    pub path: PathBuf,
+    pub prefix: PathBuf,
+    pub runner: Option<Runner>,
+}
+
+impl Game for LutrisGame {
+    fn app_id(&self) -> u32 {
+        self.app_id
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn path(&self) -> &Path {
+        &self.path
+    }
+    fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+    fn runner(&self) -> Option<&Runner> {
+        self.runner.as_ref()
+    }
+}
+
+impl PrefixRunner for LutrisGame {
+    fn run_in_prefix(
+        &self,
+        exe_to_launch: PathBuf,
+        args: Option<Vec<String>>,
+        env: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let wine = self
+            .runner
+            .as_ref()
+            .with_context(|| format!("Couldn't find runner for game: {self:#?}"))?;
+        run_in_wine_prefix(exe_to_launch, &self.prefix, wine, args, env)
+    }
+}
+
+/// Build a [`LutrisGame`] from a game the detector found in Lutris.
+///
+/// `app_id` is the GOG app ID of the edition Lutris installed.
+pub fn get_game(app_id: u32, game: &lib_game_detector::data::Game) -> Result<LutrisGame> {
+    let (data_path, config_path) = get_lutris_paths();
+    get_game_from(app_id, game, &data_path, &config_path)
+}
+
+/// Look `game` up in the Lutris install with the given data and config dirs
+fn get_game_from(
+    app_id: u32,
+    game: &lib_game_detector::data::Game,
+    data_path: &Path,
+    config_path: &Path,
+) -> Result<LutrisGame> {
+    let pga_db = data_path.join("pga.db");
+    let connection = Connection::open_with_flags(&pga_db, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", pga_db.display()))?;
+    let (slug, runner_type, configpath, directory): (String, Option<String>, String, Option<String>) =
+        connection
+            .query_row(
+                "SELECT slug, runner, configpath, directory FROM games WHERE name = ?1 AND installed = 1",
+                [&game.title],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .with_context(|| format!("Couldn't find '{}' in {}", game.title, pga_db.display()))?;
+
+    if runner_type.as_deref() != Some("wine") {
+        bail!(
+            "Lutris game '{}' uses the '{}' runner, only 'wine' is supported",
+            game.title,
+            runner_type.unwrap_or_default()
+        );
+    }
+
+    // Game configs moved from the config dir to the data dir in Lutris 0.5.13
+    let game_yml_path = [data_path.join("games"), config_path.join("games")]
+        .iter()
+        .map(|dir| dir.join(format!("{configpath}.yml")))
+        .find(|path| path.is_file())
+        .with_context(|| format!("Couldn't find Lutris config '{configpath}.yml'"))?;
+    let game_yml: Value = serde_yaml::from_str(
+        &std::fs::read_to_string(&game_yml_path)
+            .with_context(|| format!("Failed to read {}", game_yml_path.display()))?,
+    )
+    .with_context(|| format!("Failed to parse {}", game_yml_path.display()))?;
+
+    let prefix = game_yml["game"]["prefix"]
+        .as_str()
+        .map(PathBuf::from)
+        .context("Lutris game config missing game.prefix")?;
+    let mut exe = game_yml["game"]["exe"]
+        .as_str()
+        .map(PathBuf::from)
+        .context("Lutris game config missing game.exe")?;
+    if exe.is_relative() {
+        let game_dir = directory
+            .map(PathBuf::from)
+            .or_else(|| game.path_game_dir.clone())
+            .context("Lutris game has a relative exe but no directory")?;
+        exe = game_dir.join(exe);
+    }
+    let path = exe
+        .parent()
+        .context("Couldn't get directory of Lutris game exe")?
+        .to_path_buf();
+
+    // Fall back to the wine runner's default version when the game doesn't pin one
+    let wine_version = match game_yml["wine"]["version"].as_str() {
+        Some(version) => Some(version.to_string()),
+        None => std::fs::read_to_string(config_path.join("runners/wine.yml"))
+            .ok()
+            .and_then(|s| serde_yaml::from_str::<Value>(&s).ok())
+            .and_then(|runner_yml| runner_yml["wine"]["version"].as_str().map(String::from)),
+    };
+
+    Ok(LutrisGame {
+        app_id,
+        name: game.title.clone(),
+        slug,
+        path,
+        prefix,
+        runner: Some(get_runner(data_path, wine_version.as_deref())?),
+    })
+}
+
+fn get_runner(data_path: &Path, version: Option<&str>) -> Result<Runner> {
+    let Some(version) = version.filter(|v| *v != "system") else {
+        let wine_bin = std::env::var_os("PATH")
+            .into_iter()
+            .flat_map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+            .map(|dir| dir.join("wine"))
+            .find(|path| path.is_file())
+            .context("Lutris game uses system wine, but no wine binary is in PATH")?;
+        return Ok(Runner {
+            name: "wine".to_string(),
+            pretty_name: "System wine".to_string(),
+            path: wine_bin,
+            runtime: None,
+        });
+    };
+
+    let wine_bin = data_path
+        .join("runners/wine")
+        .join(version)
+        .join("bin/wine");
+    if wine_bin.is_file() {
+        return Ok(Runner {
+            name: "wine".to_string(),
+            pretty_name: version.to_string(),
+            path: wine_bin,
+            runtime: None,
+        });
+    }
+
+    // Newer Lutris runs GE-Proton builds through umu
+    let proton_bin = data_path
+        .join("runners/proton")
+        .join(version)
+        .join("proton");
+    let umu_bin = data_path.join("runtime/umu/umu-run");
+    if proton_bin.is_file() && umu_bin.is_file() {
+        log::info!("Found umu runtime at {}", umu_bin.display());
+        return Ok(Runner {
+            name: "proton".to_string(),
+            pretty_name: version.to_string(),
+            path: proton_bin,
+            runtime: Some(Runtime {
+                name: "umu".to_string(),
+                pretty_name: "umu".to_string(),
+                path: umu_bin,
+            }),
+        });
+    }
+
+    bail!(
+        "Couldn't find Lutris wine version '{version}' in {}",
+        data_path.join("runners").display()
+    )
+}
+
+/// Get paths to the Lutris data and config dirs, falling back to the flatpak version if necessary
+fn get_lutris_paths() -> (PathBuf, PathBuf) {
+    let path_home = home::home_dir().expect("Failed to get home directory");
+    let xdg_dirs = xdg::BaseDirectories::new();
+    let path_data = xdg_dirs
+        .data_home
+        .unwrap_or(path_home.join(".local/share"))
+        .join("lutris");
+    let path_config = xdg_dirs
+        .config_home
+        .unwrap_or(path_home.join(".config"))
+        .join("lutris");
+
+    if !path_data.join("pga.db").is_file() {
+        log::info!("Lutris - Attempting to fall back to flatpak");
+        let path_flatpak = path_home.join(".var/app/net.lutris.Lutris");
+        return (
+            path_flatpak.join("data/lutris"),
+            path_flatpak.join("config/lutris"),
+        );
+    }
+
+    (path_data, path_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib_game_detector::data::{Game as DetectedGame, SupportedLaunchers};
+    use std::process::Command;
+
+    const GOG_APP_ID: u32 = 1698970154;
+
+    /// A row of Lutris' `games` table: name, slug, runner, configpath, directory, installed
+    type GameRow<'a> = (&'a str, &'a str, &'a str, &'a str, Option<&'a str>, bool);
+
+    /// A Lutris install with a `pga.db` holding `games`
+    fn lutris(games: &[GameRow]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("data/games")).unwrap();
+        std::fs::create_dir_all(dir.path().join("config/games")).unwrap();
+        let connection = Connection::open(dir.path().join("data/pga.db")).unwrap();
+        connection
+            .execute(
+                "CREATE TABLE games (id INTEGER PRIMARY KEY, name TEXT, slug TEXT, runner TEXT, \
+                 configpath TEXT, directory TEXT, installed INTEGER)",
+                [],
+            )
+            .unwrap();
+        for (name, slug, runner, configpath, directory, installed) in games {
+            connection
+                .execute(
+                    "INSERT INTO games (name, slug, runner, configpath, directory, installed) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    rusqlite::params![name, slug, runner, configpath, directory, installed],
+                )
+                .unwrap();
+        }
+        dir
+    }
+
+    fn detected(title: &str) -> DetectedGame {
+        DetectedGame {
+            title: title.to_string(),
+            path_icon: None,
+            path_box_art: None,
+            path_game_dir: None,
+            launch_command: Command::new("lutris"),
+            source: SupportedLaunchers::Lutris,
+        }
+    }
+
+    fn write(path: PathBuf, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn get(dir: &Path, title: &str) -> Result<LutrisGame> {
+        get_game_from(
+            GOG_APP_ID,
+            &detected(title),
+            &dir.join("data"),
+            &dir.join("config"),
+        )
+    }
+
+    #[test]
+    fn game_yml_gives_exe_prefix_and_wine() {
+        let dir = lutris(&[(
+            "FINAL FANTASY VII",
+            "final-fantasy-vii",
+            "wine",
+            "final-fantasy-vii-1700000000",
+            Some("/games/ff7"),
+            true,
+        )]);
+        write(
+            dir.path()
+                .join("data/games/final-fantasy-vii-1700000000.yml"),
+            "game:\n  exe: bin/ff7_en.exe\n  prefix: /games/ff7/prefix\n\
+             wine:\n  version: wine-ge-8-26\n",
+        );
+        write(
+            dir.path().join("data/runners/wine/wine-ge-8-26/bin/wine"),
+            "",
+        );
+
+        let game = get(dir.path(), "FINAL FANTASY VII").unwrap();
+        assert_eq!(game.app_id, GOG_APP_ID);
+        assert_eq!(game.slug, "final-fantasy-vii");
+        assert_eq!(game.path, Path::new("/games/ff7/bin"));
+        assert_eq!(game.prefix, Path::new("/games/ff7/prefix"));
+        let runner = game.runner.unwrap();
+        assert_eq!(runner.pretty_name, "wine-ge-8-26");
+        assert_eq!(
+            runner.path,
+            dir.path().join("data/runners/wine/wine-ge-8-26/bin/wine")
+        );
+    }
+
+    #[test]
+    fn old_game_yml_uses_the_runner_default() {
+        let dir = lutris(&[(
+            "FINAL FANTASY VII",
+            "final-fantasy-vii",
+            "wine",
+            "final-fantasy-vii-1600000000",
+            None,
+            true,
+        )]);
+        // Before Lutris 0.5.13 game configs lived in the config dir
+        write(
+            dir.path()
+                .join("config/games/final-fantasy-vii-1600000000.yml"),
+            "game:\n  exe: /games/ff7/ff7_en.exe\n  prefix: /games/prefix\n",
+        );
+        write(
+            dir.path().join("config/runners/wine.yml"),
+            "wine:\n  version: lutris-7.2\n",
+        );
+        write(dir.path().join("data/runners/wine/lutris-7.2/bin/wine"), "");
+
+        let game = get(dir.path(), "FINAL FANTASY VII").unwrap();
+        assert_eq!(game.path, Path::new("/games/ff7"));
+        assert_eq!(game.prefix, Path::new("/games/prefix"));
+        assert_eq!(game.runner.unwrap().pretty_name, "lutris-7.2");
+    }
+
+    #[test]
+    fn only_installed_wine_games_are_found() {
+        let dir = lutris(&[
+            (
+                "Uninstalled",
+                "uninstalled",
+                "wine",
+                "uninstalled",
+                None,
+                false,
+            ),
+            ("Native", "native", "linux", "native", None, true),
+        ]);
+        assert!(get(dir.path(), "Uninstalled").is_err());
+        assert!(get(dir.path(), "Missing").is_err());
+        let err = get(dir.path(), "Native").unwrap_err();
+        assert!(err.to_string().contains("'linux' runner"));
+    }
+}
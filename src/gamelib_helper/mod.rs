@@ -1,8 +1,13 @@
-use anyhow::{Context, Result};
+use crate::config_handler::{Config, InstallType};
+use crate::editions;
+use crate::mod_loader::ModLoader;
+use anyhow::{bail, Context, Result};
+use lib_game_detector::data::SupportedLaunchers;
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::process::{ChildStderr, ChildStdout};
+use std::process::{ChildStderr, ChildStdout, Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 
@@ -32,7 +37,13 @@ pub trait Game {
 }
 
 pub trait PrefixRunner {
-    fn run_in_prefix(&self, exe_to_launch: PathBuf, args: Option<Vec<String>>) -> Result<()>;
+    /// Run `exe_to_launch` in the game's prefix with the install's environment variables `env`
+    fn run_in_prefix(
+        &self,
+        exe_to_launch: PathBuf,
+        args: Option<Vec<String>>,
+        env: &BTreeMap<String, String>,
+    ) -> Result<()>;
 }
 
 pub trait PrefixedGame: Game + PrefixRunner {}
@@ -59,22 +70,18 @@ pub fn spawn_wine_log_threads(
 
     let log_out = Arc::clone(&wine_log);
     let stdout_handle = thread::spawn(move || {
-        for line in BufReader::new(stdout).lines() {
-            if let Ok(line) = line {
-                if let Ok(mut f) = log_out.lock() {
-                    let _ = writeln!(f, "{line}");
-                }
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Ok(mut f) = log_out.lock() {
+                let _ = writeln!(f, "{line}");
             }
         }
     });
 
     let log_err = Arc::clone(&wine_log);
     let stderr_handle = thread::spawn(move || {
-        for line in BufReader::new(stderr).lines() {
-            if let Ok(line) = line {
-                if let Ok(mut f) = log_err.lock() {
-                    let _ = writeln!(f, "{line}");
-                }
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            if let Ok(mut f) = log_err.lock() {
+                let _ = writeln!(f, "{line}");
             }
         }
     });
@@ -82,7 +89,155 @@ pub fn spawn_wine_log_threads(
     Ok((stdout_handle, stderr_handle))
 }
 
+/// Find the game an installation was set up for, from its config
+pub fn from_config(config: &Config, loader: &dyn ModLoader) -> Result<Box<dyn PrefixedGame>> {
+    let install_type = config.install_type;
+    let detected = |launcher: SupportedLaunchers, name: &str| {
+        lib_game_detector::get_detector()
+            .get_all_detected_games_from_specific_launcher(launcher)
+            .into_iter()
+            .flatten()
+            .find(|game| loader.matches_title(&game.title))
+            .with_context(|| {
+                format!("Configured type={install_type}, but {name} doesn't list the game")
+            })
+    };
+    let required = |value: &Option<PathBuf>, key: &str| {
+        value
+            .clone()
+            .with_context(|| format!("Configured type={install_type}, but {key} is missing"))
+    };
+
+    match install_type {
+        InstallType::Gog => {
+            let heroic_game = detected(SupportedLaunchers::HeroicGamesGOG, "Heroic")?;
+            let game = gog_game::get_game(config.app_id, &heroic_game)
+                .context("Configured type=gog, but GOG game was not found")?;
+            Ok(Box::new(game))
+        }
+        InstallType::Lutris => {
+            let lutris_game = detected(SupportedLaunchers::Lutris, "Lutris")?;
+            let game = lutris_game::get_game(config.app_id, &lutris_game)
+                .context("Configured type=lutris, but Lutris game was not found")?;
+            Ok(Box::new(game))
+        }
+        InstallType::Custom => {
+            let edition = editions::by_app_id(config.app_id)?;
+            let game = custom_game::get_game(
+                edition,
+                &required(&config.game_dir, "game_dir")?,
+                &required(&config.prefix, "prefix")?,
+                &required(&config.wine, "wine")?,
+            )
+            .context("Configured type=custom, but the game couldn't be found")?;
+            Ok(Box::new(game))
+        }
+        InstallType::Steam => {
+            let steam_dir =
+                steamlocate::SteamDir::from_dir(&required(&config.steam_dir, "steam_dir")?)?;
+            log::info!("Steam path: {}", steam_dir.path().display());
+            log::info!("App ID: {}", config.app_id);
+
+            let mut game = steam_game::get_game(config.app_id, steam_dir.clone())
+                .context(format!("Couldn't find {} in Steam library", config.app_id))?;
+            game.runner = Some(steam_game::get_runner(&game, config.runner.as_deref())?);
+            Ok(Box::new(game))
+        }
+    }
+}
+
+/// Run an exe in a plain Wine prefix with Wine, GE-Proton, or GE-Proton through umu
+pub fn run_in_wine_prefix(
+    exe_to_launch: PathBuf,
+    prefix: &Path,
+    wine: &Runner,
+    args: Option<Vec<String>>,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    log::info!("Using runner: {}", wine.pretty_name);
+    log::info!("Runner bin: {}", wine.path.display());
+    log::info!("Wine prefix: {}", prefix.display());
+    log::info!("Using umu runtime: {}", wine.runtime.is_some());
+
+    let mut command = match (&wine.runtime, wine.name.as_str()) {
+        (Some(umu), _) => {
+            let mut cmd = Command::new(&umu.path);
+            cmd.env("WINEPREFIX", prefix).env(
+                "PROTONPATH",
+                wine.path
+                    .parent()
+                    .context("Failed to get parent of wine path")?,
+            );
+            cmd
+        }
+        (None, "wine") => {
+            let mut cmd = Command::new(&wine.path);
+            cmd.env("WINEPREFIX", prefix);
+            cmd
+        }
+        (None, "proton") => {
+            let mut cmd = Command::new(&wine.path);
+            if !wine.pretty_name.contains("GE") {
+                bail!("Found proton runner, but it doesn't seem to be GE-Proton. Runner: {wine:?}");
+            }
+            // Proton runs in the `pfx` directory of the path it's given
+            cmd.env(
+                "STEAM_COMPAT_DATA_PATH",
+                prefix
+                    .parent()
+                    .context("Couldn't get parent of prefix directory")?,
+            )
+            .env("SteamGameId", "0")
+            .arg("runinprefix");
+            cmd
+        }
+        (None, other) => bail!("Unknown runner type: {other}"),
+    };
+    command
+        .env("WINEDLLOVERRIDES", "dinput=n,b")
+        .envs(env)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .arg(&exe_to_launch);
+    let args = args.unwrap_or_default();
+    for arg in args {
+        log::info!("run_in_prefix arg: {arg}");
+        command.arg(arg);
+    }
+
+    let mut child = command.spawn()?;
+    log::info!(
+        "Launched {}",
+        exe_to_launch
+            .file_name()
+            .context("Couldn't get file_name")?
+            .to_string_lossy()
+    );
+
+    let stdout = child.stdout.take().context("Failed to capture stdout")?;
+    let stderr = child.stderr.take().context("Failed to capture stderr")?;
+
+    let (stdout_thread, stderr_thread) = spawn_wine_log_threads(stdout, stderr)?;
+
+    let status = child.wait()?;
+
+    stdout_thread.join().expect("Failed to join stdout thread");
+    stderr_thread.join().expect("Failed to join stderr thread");
+
+    if status.success() {
+        log::info!("Process exited successfully");
+        Ok(())
+    } else {
+        bail!("Process exited with an error: {status}");
+    }
+}
+
+pub mod artwork;
+pub mod custom_game;
 pub mod gog_game;
+pub mod lutris_game;
+pub mod shortcuts;
 pub mod steam_game;
 pub mod steam_lib;
 pub mod steam_proton;
+pub mod vdf;
@@ -0,0 +1,412 @@
+use crate::manifest::{self, FileKind};
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// A value in Steam's binary KeyValues format
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Map(Vec<(String, Value)>),
+    /// Kept as raw bytes: Steam writes names and paths in whatever encoding it was given
+    Str(Vec<u8>),
+    Int(u32),
+    Float(f32),
+    Uint64(u64),
+}
+
+const MAP: u8 = 0x00;
+const STR: u8 = 0x01;
+const INT: u8 = 0x02;
+const FLOAT: u8 = 0x03;
+const UINT64: u8 = 0x07;
+const MAP_END: u8 = 0x08;
+
+/// A non-Steam game shortcut
+#[derive(Debug, Clone)]
+pub struct Shortcut {
+    pub app_name: String,
+    pub exe: PathBuf,
+    pub start_dir: PathBuf,
+    pub icon: Option<PathBuf>,
+    pub launch_options: String,
+    pub tags: Vec<String>,
+}
+
+impl Shortcut {
+    /// The 32-bit app ID Steam gives this shortcut, as used for its artwork and
+    /// `CompatToolMapping`
+    pub fn app_id(&self) -> u32 {
+        app_id(&quote(&self.exe), &self.app_name)
+    }
+
+    /// The 64-bit game ID Steam uses for this shortcut elsewhere, e.g. in controller configs
+    pub fn game_id(&self) -> u64 {
+        game_id(self.app_id())
+    }
+}
+
+/// Steam's app ID for a shortcut to `exe` (quoted, as in `shortcuts.vdf`) named `app_name`
+pub fn app_id(exe: &str, app_name: &str) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(exe.as_bytes());
+    hasher.update(app_name.as_bytes());
+    hasher.finalize() | 0x8000_0000
+}
+
+/// The 64-bit game ID for the shortcut app ID `app_id`
+pub fn game_id(app_id: u32) -> u64 {
+    ((app_id as u64) << 32) | 0x0200_0000
+}
+
+/// A Steam user's `userdata/<id>/config/shortcuts.vdf`, with the entries MateriaForge doesn't
+/// touch kept as they are
+#[derive(Debug, Clone)]
+pub struct Shortcuts {
+    pub path: PathBuf,
+    root: Vec<(String, Value)>,
+}
+
+impl Shortcuts {
+    /// The shortcuts in `path`; a user without any has no file yet, or an empty one
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = if path.is_file() {
+            std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+        if bytes.is_empty() {
+            return Ok(Shortcuts {
+                path: path.to_path_buf(),
+                root: vec![("shortcuts".to_string(), Value::Map(Vec::new()))],
+            });
+        }
+        let mut reader = Reader {
+            bytes: &bytes,
+            pos: 0,
+        };
+        let root = reader
+            .map()
+            .with_context(|| format!("Couldn't parse {}", path.display()))?;
+        Ok(Shortcuts {
+            path: path.to_path_buf(),
+            root,
+        })
+    }
+
+    /// The app ID of the shortcut to `exe`, if there is one
+    pub fn app_id(&self, exe: &Path) -> Option<u32> {
+        match get(self.find(exe)?, "appid")? {
+            Value::Int(app_id) => Some(*app_id),
+            _ => None,
+        }
+    }
+
+    /// Add a shortcut, or update the one that already launches the same executable. An updated
+    /// shortcut keeps its app ID, which Steam may have assigned itself.
+    pub fn upsert(&mut self, shortcut: &Shortcut) -> Result<()> {
+        let icon = shortcut
+            .icon
+            .as_ref()
+            .map(|icon| icon.display().to_string());
+        let tags = shortcut
+            .tags
+            .iter()
+            .enumerate()
+            .map(|(i, tag)| (i.to_string(), Value::str(tag)))
+            .collect();
+        let fields = [
+            ("appid", Value::Int(shortcut.app_id())),
+            ("AppName", Value::str(&shortcut.app_name)),
+            ("Exe", Value::str(quote(&shortcut.exe))),
+            ("StartDir", Value::str(quote(&shortcut.start_dir))),
+            ("icon", Value::str(icon.unwrap_or_default())),
+            ("LaunchOptions", Value::str(&shortcut.launch_options)),
+        ];
+
+        let index = self.position(&shortcut.exe);
+        let entries = self.entries_mut()?;
+        let Some(index) = index else {
+            // Laid out like the shortcuts Steam adds itself
+            let entry = fields
+                .into_iter()
+                .chain([
+                    ("IsHidden", Value::Int(0)),
+                    ("AllowDesktopConfig", Value::Int(1)),
+                    ("AllowOverlay", Value::Int(1)),
+                    ("OpenVR", Value::Int(0)),
+                    ("Devkit", Value::Int(0)),
+                    ("DevkitGameID", Value::str("")),
+                    ("DevkitOverrideAppID", Value::Int(0)),
+                    ("LastPlayTime", Value::Int(0)),
+                    ("FlatpakAppID", Value::str("")),
+                    ("tags", Value::Map(tags)),
+                ])
+                .map(|(key, value)| (key.to_string(), value))
+                .collect();
+            entries.push((entries.len().to_string(), Value::Map(entry)));
+            return Ok(());
+        };
+        let Value::Map(entry) = &mut entries[index].1 else {
+            bail!("Shortcut {index} isn't a map");
+        };
+        for (key, value) in fields.into_iter().chain([("tags", Value::Map(tags))]) {
+            match entry.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+                // Its artwork and playtime are kept under the ID it already has
+                Some(_) if key == "appid" => {}
+                Some((_, existing)) => *existing = value,
+                None => entry.push((key.to_string(), value)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the shortcut to `exe`, returning whether there was one
+    pub fn remove(&mut self, exe: &Path) -> Result<bool> {
+        let Some(index) = self.position(exe) else {
+            return Ok(false);
+        };
+        let entries = self.entries_mut()?;
+        entries.remove(index);
+        // Steam expects the entries to be numbered from 0 without gaps
+        for (i, (key, _)) in entries.iter_mut().enumerate() {
+            *key = i.to_string();
+        }
+        Ok(true)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Couldn't create {}", parent.display()))?;
+        }
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &self.root);
+        manifest::write(&self.path, &bytes, FileKind::SteamConfig)
+    }
+
+    fn entries(&self) -> &[(String, Value)] {
+        match get_entry(&self.root, "shortcuts") {
+            Some(Value::Map(entries)) => entries,
+            _ => &[],
+        }
+    }
+
+    fn entries_mut(&mut self) -> Result<&mut Vec<(String, Value)>> {
+        if get_entry(&self.root, "shortcuts").is_none() {
+            self.root
+                .push(("shortcuts".to_string(), Value::Map(Vec::new())));
+        }
+        match self
+            .root
+            .iter_mut()
+            .find(|(key, _)| key.eq_ignore_ascii_case("shortcuts"))
+        {
+            Some((_, Value::Map(entries))) => Ok(entries),
+            _ => bail!("`shortcuts` in {} isn't a map", self.path.display()),
+        }
+    }
+
+    fn position(&self, exe: &Path) -> Option<usize> {
+        self.entries()
+            .iter()
+            .position(|(_, entry)| match get(entry, "Exe") {
+                Some(Value::Str(found)) => {
+                    Path::new(String::from_utf8_lossy(found).trim_matches('"')) == exe
+                }
+                _ => false,
+            })
+    }
+
+    fn find(&self, exe: &Path) -> Option<&Value> {
+        Some(&self.entries()[self.position(exe)?].1)
+    }
+}
+
+impl Value {
+    fn str(string: impl Into<String>) -> Self {
+        Value::Str(string.into().into_bytes())
+    }
+}
+
+fn quote(path: &Path) -> String {
+    format!("\"{}\"", path.display())
+}
+
+fn get_entry<'a>(map: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    map.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value)
+}
+
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Map(map) => get_entry(map, key),
+        _ => None,
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn map(&mut self) -> Result<Vec<(String, Value)>> {
+        let mut map = Vec::new();
+        loop {
+            let kind = self.take(1)?[0];
+            if kind == MAP_END {
+                return Ok(map);
+            }
+            let key = String::from_utf8_lossy(self.string()?).into_owned();
+            let value = match kind {
+                MAP => Value::Map(self.map()?),
+                STR => Value::Str(self.string()?.to_vec()),
+                INT => Value::Int(u32::from_le_bytes(self.take(4)?.try_into()?)),
+                FLOAT => Value::Float(f32::from_le_bytes(self.take(4)?.try_into()?)),
+                UINT64 => Value::Uint64(u64::from_le_bytes(self.take(8)?.try_into()?)),
+                kind => bail!("Unsupported value type {kind:#04x} for `{key}`"),
+            };
+            map.push((key, value));
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .context("Unexpected end of file")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<&[u8]> {
+        let rest = &self.bytes[self.pos..];
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .context("Unterminated string")?;
+        self.pos += len + 1;
+        Ok(&rest[..len])
+    }
+}
+
+fn write_map(bytes: &mut Vec<u8>, map: &[(String, Value)]) {
+    for (key, value) in map {
+        let kind = match value {
+            Value::Map(_) => MAP,
+            Value::Str(_) => STR,
+            Value::Int(_) => INT,
+            Value::Float(_) => FLOAT,
+            Value::Uint64(_) => UINT64,
+        };
+        bytes.push(kind);
+        write_string(bytes, key.as_bytes());
+        match value {
+            Value::Map(map) => write_map(bytes, map),
+            Value::Str(string) => write_string(bytes, string),
+            Value::Int(int) => bytes.extend(int.to_le_bytes()),
+            Value::Float(float) => bytes.extend(float.to_le_bytes()),
+            Value::Uint64(int) => bytes.extend(int.to_le_bytes()),
+        }
+    }
+    bytes.push(MAP_END);
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &[u8]) {
+    bytes.extend(string);
+    bytes.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `shortcuts.vdf` written by Steam on Linux, from steamlocate's test assets (MIT)
+    const SAMPLE: &[u8] = include_bytes!("testdata/shortcuts.vdf");
+
+    fn parse(bytes: &[u8]) -> Vec<(String, Value)> {
+        Reader { bytes, pos: 0 }.map().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &parse(SAMPLE));
+        assert_eq!(bytes, SAMPLE);
+    }
+
+    #[test]
+    fn ids_match_steam() {
+        // The IDs steamlocate's tests expect for the shortcuts in SAMPLE (MIT)
+        let expected = [
+            ("\"anki\"", "Anki", 0xe896_14fe_0200_0000),
+            ("\"libreoffice\"", "LibreOffice Calc", 0xdb01_c799_0200_0000),
+            ("\"/usr/local/bin/foo.sh\"", "foo.sh", 0x9d55_0173_0200_0000),
+        ];
+        for (exe, app_name, expected) in expected {
+            assert_eq!(game_id(app_id(exe, app_name)), expected, "{app_name}");
+        }
+
+        let shortcut = Shortcut {
+            app_name: "foo.sh".to_string(),
+            exe: PathBuf::from("/usr/local/bin/foo.sh"),
+            start_dir: PathBuf::from("/usr/local/bin/"),
+            icon: None,
+            launch_options: String::new(),
+            tags: Vec::new(),
+        };
+        assert_eq!(shortcut.app_id(), 0x9d55_0173);
+        assert_eq!(shortcut.game_id(), 0x9d55_0173_0200_0000);
+    }
+
+    #[test]
+    fn upsert_keeps_app_id() {
+        let mut shortcuts = Shortcuts {
+            path: PathBuf::new(),
+            root: parse(SAMPLE),
+        };
+        let exe = PathBuf::from("/usr/local/bin/foo.sh");
+        // Steam assigned this one an ID other than the computed one
+        assert_eq!(shortcuts.app_id(&exe), Some(3703025501));
+        shortcuts
+            .upsert(&Shortcut {
+                app_name: "Foo".to_string(),
+                exe: exe.clone(),
+                start_dir: PathBuf::from("/usr/local/bin/"),
+                icon: None,
+                launch_options: "--bar".to_string(),
+                tags: Vec::new(),
+            })
+            .unwrap();
+        assert_eq!(shortcuts.app_id(&exe), Some(3703025501));
+        assert!(matches!(
+            get(shortcuts.find(&exe).unwrap(), "LaunchOptions"),
+            Some(Value::Str(options)) if options == b"--bar"
+        ));
+    }
+
+    #[test]
+    fn upsert_then_remove_restores_file() {
+        let mut shortcuts = Shortcuts {
+            path: PathBuf::new(),
+            root: parse(SAMPLE),
+        };
+        let exe = PathBuf::from("/home/deck/Games/7th Heaven/Launch 7th Heaven");
+        shortcuts
+            .upsert(&Shortcut {
+                app_name: "7th Heaven".to_string(),
+                exe: exe.clone(),
+                start_dir: PathBuf::from("/home/deck/Games/7th Heaven"),
+                icon: None,
+                launch_options: String::new(),
+                tags: vec!["MateriaForge".to_string()],
+            })
+            .unwrap();
+        assert!(shortcuts.app_id(&exe).is_some());
+        assert!(shortcuts.remove(&exe).unwrap());
+
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &shortcuts.root);
+        assert_eq!(bytes, SAMPLE);
+    }
+}
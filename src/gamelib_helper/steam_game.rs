@@ -1,13 +1,13 @@
-use crate::config_handler;
-use crate::gamelib_helper::{spawn_wine_log_threads, steam_proton, Game, PrefixRunner, Runner};
+use crate::gamelib_helper::{
+    spawn_wine_log_threads, steam_proton, vdf::Vdf, Game, PrefixRunner, Runner,
+};
 use std::{
-    fs,
+    collections::BTreeMap,
     path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 
 use anyhow::{bail, Context, Result};
-use regex::Regex;
 
 #[derive(Debug, Clone)]
 pub struct SteamGame {
@@ -38,8 +38,13 @@ impl Game for SteamGame {
 }
 
 impl PrefixRunner for SteamGame {
-    fn run_in_prefix(&self, exe_to_launch: PathBuf, args: Option<Vec<String>>) -> Result<()> {
-        run_in_prefix(exe_to_launch, self, args)
+    fn run_in_prefix(
+        &self,
+        exe_to_launch: PathBuf,
+        args: Option<Vec<String>>,
+        env: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        run_in_prefix(exe_to_launch, self, args, env)
     }
 }
 
@@ -89,10 +94,12 @@ pub fn select_runner(game: &SteamGame) -> Result<Runner> {
     Ok(selected)
 }
 
-pub fn get_runner(game: &SteamGame) -> Result<Runner> {
+/// The Proton version to run `game` with: `configured` if it's installed, else the one Steam
+/// has set for the game
+pub fn get_runner(game: &SteamGame, configured: Option<&str>) -> Result<Runner> {
     let steam_dir = steamlocate::SteamDir::from_dir(&game.client_path)?;
 
-    if let Some(runner_name) = config_handler::read_value("runner").ok() {
+    if let Some(runner_name) = configured {
         log::info!("Runner specified in config: {runner_name}");
         if let Ok(versions) = steam_proton::find_all_versions(steam_dir.clone()) {
             if let Some(runner) = versions.into_iter().find(|r| r.name == runner_name) {
@@ -129,6 +136,7 @@ pub fn run_in_prefix(
     exe_to_launch: PathBuf,
     game: &SteamGame,
     args: Option<Vec<String>>,
+    env: &BTreeMap<String, String>,
 ) -> Result<()> {
     let mut command: Command;
 
@@ -142,8 +150,8 @@ pub fn run_in_prefix(
 
     // Build STEAM_COMPAT_MOUNTS
     let ancestor = |path: &Path, levels: usize| -> PathBuf {
-        (0..levels)
-            .fold(Some(path), |p, _| p.and_then(Path::parent))
+        path.ancestors()
+            .nth(levels)
             .map(Path::to_path_buf)
             .unwrap_or_default()
     };
@@ -202,7 +210,7 @@ pub fn run_in_prefix(
         .env("STEAM_COMPAT_LIBRARY_PATHS", library_path)
         .env("PROTON_SET_GAME_DRIVE", "1")
         .env("WINEDLLOVERRIDES", "dinput=n,b")
-        .envs(config_handler::read_env_vars())
+        .envs(env)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .arg("waitforexitandrun")
@@ -233,41 +241,53 @@ pub fn run_in_prefix(
     stderr_thread.join().expect("Failed to join stderr thread");
 
     if status.success() {
-        Ok(log::info!("Process exited successfully"))
+        log::info!("Process exited successfully");
+        Ok(())
     } else {
         bail!("Process exited with an error: {status}");
     }
 }
 
 pub fn set_runner(game: &SteamGame, runner: &str) -> Result<()> {
-    let re = Regex::new(r#""CompatToolMapping"\s*\{"#)?;
-    let replacement = format!(
-        r#""CompatToolMapping"
-                {{
-                    "{}"
-                    {{
-                        "name"		"{}"
-                        "config"		""
-                        "priority"		"250"
-                    }}
-"#,
-        &game.app_id, runner
-    );
+    set_compat_tool(&game.client_path, game.app_id, Some(runner))
+}
 
-    let remove_re = Regex::new(&format!(
-        r#""{}"[^{{]*\{{[^}}]*"name"[^}}]*\}}"#,
-        &game.app_id
-    ))?;
+/// Where `config.vdf` keeps the Proton version of each app
+const COMPAT_TOOL_MAPPING: [&str; 5] = [
+    "InstallConfigStore",
+    "Software",
+    "Valve",
+    "Steam",
+    "CompatToolMapping",
+];
 
-    let path = &game.client_path.join("config/config.vdf");
-    let content = fs::read_to_string(path)?;
-    let content = remove_re.replace(&content, "").to_string();
-    fs::write(path, re.replace(&content, &replacement).as_bytes())
-        .with_context(|| format!("Couldn't write to {path:?}"))?;
-    log::info!(
-        "Succcessfully set runner for {} to {}",
-        &game.app_id,
-        runner
-    );
+/// Point Steam's `CompatToolMapping` for `app_id` at `runner`, or drop the mapping with `None`
+pub fn set_compat_tool(client_path: &Path, app_id: u32, runner: Option<&str>) -> Result<()> {
+    let mut config = Vdf::load(&client_path.join("config/config.vdf"))?;
+    let app_id = app_id.to_string();
+    let mapping = [&COMPAT_TOOL_MAPPING[..], &[&app_id]].concat();
+    match runner {
+        Some(runner) => {
+            for (key, value) in [("name", runner), ("config", ""), ("priority", "250")] {
+                config.set(&[&mapping[..], &[key]].concat(), value)?;
+            }
+        }
+        None => {
+            config.remove(&mapping)?;
+        }
+    }
+    config.save()?;
+    match runner {
+        Some(runner) => log::info!("Succcessfully set runner for {app_id} to {runner}"),
+        None => log::info!("Removed runner for {app_id}"),
+    }
     Ok(())
 }
+
+/// Name of the compat tool Steam currently runs `app_id` with, if one is set
+pub fn compat_tool(client_path: &Path, app_id: u32) -> Option<String> {
+    Vdf::load(&client_path.join("config/config.vdf"))
+        .ok()?
+        .get(&[&COMPAT_TOOL_MAPPING[..], &[&app_id.to_string(), "name"]].concat())
+        .map(String::from)
+}
@@ -1,12 +1,15 @@
-use anyhow::{Context, Result};
-use dialoguer::theme::ColorfulTheme;
-use regex::Regex;
+use crate::answers;
+use crate::editions::{Edition, Store};
+use crate::gamelib_helper::shortcuts::{self, Shortcut, Shortcuts};
+use crate::gamelib_helper::vdf::Vdf;
+use crate::manifest::{self, Change};
+use anyhow::{bail, Context, Result};
 use std::{
-    fs::OpenOptions,
+    collections::BTreeMap,
     path::{Path, PathBuf},
     process::{Command, Stdio},
+    time::Duration,
 };
-use urlencoding::encode;
 
 pub fn get_library() -> Result<steamlocate::SteamDir> {
     let home_dir = home::home_dir().expect("Couldn't get $HOME?");
@@ -22,6 +25,9 @@ pub fn get_library() -> Result<steamlocate::SteamDir> {
         .filter(|path| path.exists())
         .collect();
 
+    if libraries.is_empty() {
+        bail!("Couldn't find a Steam installation");
+    }
     if libraries.len() == 1 {
         let library = steamlocate::SteamDir::from_dir(libraries[0].as_path())
             .context("Couldn't get library")?;
@@ -45,11 +51,13 @@ pub fn get_library() -> Result<steamlocate::SteamDir> {
             console::style(libraries[1].display()).bold().underlined()
         ),
     ];
-    let selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select a Steam installation to continue:")
-        .items(choices)
-        .default(0)
-        .interact()?;
+    let selection = answers::select(
+        "steam_install",
+        "Select a Steam installation to continue:",
+        choices,
+        &["native", "flatpak"],
+        0,
+    )?;
 
     let library = steamlocate::SteamDir::from_dir(libraries[selection].as_path())
         .context("Failed to get library from dir")?;
@@ -57,132 +65,497 @@ pub fn get_library() -> Result<steamlocate::SteamDir> {
     Ok(library)
 }
 
-pub fn add_nonsteam_game(file: &Path, steam_dir: steamlocate::SteamDir) -> Result<()> {
-    let file_dir = file
-        .parent()
-        .with_context(|| format!("Couldn't get parent of {file:?}"))?;
-    let uid = uzers::get_current_uid();
-    let mut tmp = PathBuf::from("/tmp");
-    let mut steam_args: Vec<&str> = vec![];
-    let steam_bin = match steam_dir
-        .path()
-        .to_string_lossy()
-        .contains("com.valvesoftware.Steam")
-    {
-        true => "flatpak",
-        _ => "steam",
+/// Offset between a 64-bit Steam ID and the account ID `userdata/` folders are named after
+const STEAM_ID64_BASE: u64 = 76_561_197_960_265_728;
+
+/// A Steam account that has logged in on this machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SteamUser {
+    /// Account ID, the name of the account's `userdata/` folder
+    pub id: u32,
+    pub account_name: Option<String>,
+    pub persona_name: Option<String>,
+    /// Whether Steam logs in to this account by default
+    pub most_recent: bool,
+}
+
+impl SteamUser {
+    /// What `--steam-user` picks this account by: its account name, or its ID if that's unknown
+    pub fn answer_id(&self) -> String {
+        match &self.account_name {
+            Some(name) => name.clone(),
+            None => self.id.to_string(),
+        }
+    }
+
+    /// The account's `userdata/<id>/config/`, home to its shortcuts and artwork
+    pub fn config_dir(&self, steam_dir: &Path) -> PathBuf {
+        steam_dir
+            .join("userdata")
+            .join(self.id.to_string())
+            .join("config")
+    }
+
+    /// The account's controller template assignments
+    pub fn controller_config(&self, steam_dir: &Path) -> PathBuf {
+        steam_dir
+            .join("steamapps/common/Steam Controller Configs")
+            .join(self.id.to_string())
+            .join("config/configset_controller_neptune.vdf")
+    }
+}
+
+impl std::fmt::Display for SteamUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.persona_name, &self.account_name) {
+            (Some(persona), Some(account)) => write!(f, "{persona} ({account})"),
+            (Some(name), None) | (None, Some(name)) => write!(f, "{name} ({})", self.id),
+            (None, None) => write!(f, "{}", self.id),
+        }
+    }
+}
+
+/// The accounts in `config/loginusers.vdf` and `userdata/`, the one Steam uses by default first
+pub fn users(steam_dir: &Path) -> Result<Vec<SteamUser>> {
+    let mut users: Vec<SteamUser> = Vec::new();
+
+    let login_users = steam_dir.join("config/loginusers.vdf");
+    if login_users.is_file() {
+        let vdf = Vdf::load(&login_users)?;
+        for steam_id in vdf.keys(&["users"]) {
+            let Some(id) = steam_id
+                .parse::<u64>()
+                .ok()
+                .and_then(|id| id.checked_sub(STEAM_ID64_BASE))
+                .and_then(|id| u32::try_from(id).ok())
+            else {
+                log::warn!("Skipping unknown Steam ID {steam_id} in {login_users:?}");
+                continue;
+            };
+            let get = |key: &str| {
+                vdf.get(&["users", steam_id, key])
+                    .filter(|value| !value.is_empty())
+                    .map(String::from)
+            };
+            users.push(SteamUser {
+                id,
+                account_name: get("AccountName"),
+                persona_name: get("PersonaName"),
+                most_recent: get("MostRecent").as_deref() == Some("1"),
+            });
+        }
+    }
+
+    let userdata = steam_dir.join("userdata");
+    if userdata.is_dir() {
+        for entry in std::fs::read_dir(&userdata)
+            .with_context(|| format!("Couldn't read {}", userdata.display()))?
+            .flatten()
+        {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // `0` holds settings from before any account logged in
+            let Some(id) = name.parse::<u32>().ok().filter(|id| *id != 0) else {
+                continue;
+            };
+            if entry.path().is_dir() && !users.iter().any(|user| user.id == id) {
+                users.push(SteamUser {
+                    id,
+                    account_name: None,
+                    persona_name: None,
+                    most_recent: false,
+                });
+            }
+        }
+    }
+
+    users.sort_by_key(|user| (!user.most_recent, user.id));
+    Ok(users)
+}
+
+/// The accounts to add shortcuts and controller configs for, asking when there's more than one
+pub fn select_users(steam_dir: &Path) -> Result<Vec<SteamUser>> {
+    let users = users(steam_dir)?;
+    if users.len() <= 1 {
+        if users.is_empty() {
+            bail!(
+                "No Steam users found in {}; log in to Steam once first",
+                steam_dir.display()
+            );
+        }
+        return Ok(users);
+    }
+
+    let mut choices = vec!["All users".to_string()];
+    choices.extend(users.iter().map(|user| user.to_string()));
+    let answer_ids = users.iter().map(SteamUser::answer_id).collect::<Vec<_>>();
+    let mut ids = vec!["all"];
+    ids.extend(answer_ids.iter().map(String::as_str));
+    let selection = answers::select(
+        "steam_user",
+        "Select the Steam user to add the shortcut for:",
+        &choices,
+        &ids,
+        1,
+    )?;
+
+    match selection {
+        0 => Ok(users),
+        i => Ok(vec![users[i - 1].clone()]),
+    }
+}
+
+/// Whether the Steam client is running
+pub fn is_running() -> bool {
+    let mut system = sysinfo::System::new();
+    system.refresh_processes();
+    let running = system.processes_by_exact_name("steam").next().is_some();
+    running
+}
+
+/// Steam writes its shortcuts back from memory when it exits, so it has to be closed while
+/// `shortcuts.vdf` is edited. Offers to close it if it's running.
+pub fn ensure_closed(steam_dir: &Path) -> Result<()> {
+    if !is_running() {
+        return Ok(());
+    }
+    if !answers::confirm(
+        "close_steam",
+        "Steam has to be closed to change its shortcuts. Close it now?",
+    )? {
+        bail!("Close Steam and try again");
+    }
+
+    let mut command = match is_flatpak(steam_dir) {
+        true => {
+            let mut command = Command::new("flatpak");
+            command.args(["run", "com.valvesoftware.Steam"]);
+            command
+        }
+        false => Command::new("steam"),
     };
+    command
+        .arg("-shutdown")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Couldn't ask Steam to close")?;
+    for _ in 0..60 {
+        std::thread::sleep(Duration::from_millis(500));
+        if !is_running() {
+            log::info!("Steam closed");
+            return Ok(());
+        }
+    }
+    bail!("Steam didn't close in time; close it and try again")
+}
 
-    // Flatpak Steam
-    if steam_bin == "flatpak" {
-        steam_args = vec!["run", "com.valvesoftware.Steam"];
-        tmp = PathBuf::from(format!(
-            "/run/user/{uid}/.flatpak/com.valvesoftware.Steam/tmp"
-        ));
+fn is_flatpak(steam_dir: &Path) -> bool {
+    steam_dir
+        .to_string_lossy()
+        .contains("com.valvesoftware.Steam")
+}
 
-        Command::new("flatpak")
+/// Add `shortcut` to the libraries of `users`, or update it where it's already there
+pub fn add_nonsteam_game(steam_dir: &Path, users: &[SteamUser], shortcut: &Shortcut) -> Result<()> {
+    if is_flatpak(steam_dir) {
+        // Let Flatpak Steam see the install
+        let status = Command::new("flatpak")
             .args([
                 "override",
                 "--user",
-                &format!("--filesystem={}", file_dir.display()),
+                &format!("--filesystem={}", shortcut.start_dir.display()),
                 "com.valvesoftware.Steam",
             ])
-            .status()?;
-
-        Command::new("flatpak")
-            .args(["kill", "com.valvesoftware.Steam"])
-            .status()?;
+            .status()
+            .context("Couldn't run flatpak override")?;
+        if !status.success() {
+            bail!(
+                "flatpak override exited with {status}, so Flatpak Steam couldn't see {}",
+                shortcut.start_dir.display()
+            );
+        }
     }
 
-    let encoded_url = format!(
-        "steam://addnonsteamgame/{}",
-        encode(&file.to_string_lossy())
-    );
-    OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(tmp.join("addnonsteamgamefile"))?;
-
-    let status = Command::new(steam_bin)
-        .args(&steam_args)
-        .arg(&encoded_url)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()?;
-
-    if !status.success() {
-        anyhow::bail!("Steam exited with status: {status}");
+    ensure_closed(steam_dir)?;
+    for user in users {
+        let mut shortcuts = Shortcuts::load(&user.config_dir(steam_dir).join("shortcuts.vdf"))?;
+        shortcuts.upsert(shortcut)?;
+        shortcuts.save()?;
+        log::info!("Added {:?} to {}", shortcut.exe, shortcuts.path.display());
     }
+    Ok(())
+}
 
-    log::info!("Added {file:?} to Steam!");
+/// Remove the shortcut to `exe` from every Steam user's library
+pub fn remove_nonsteam_game(steam_dir: &Path, exe: &Path) -> Result<()> {
+    let mut changed = Vec::new();
+    for user in users(steam_dir)? {
+        let mut shortcuts = Shortcuts::load(&user.config_dir(steam_dir).join("shortcuts.vdf"))?;
+        if shortcuts.remove(exe)? {
+            changed.push(shortcuts);
+        }
+    }
+    if changed.is_empty() {
+        return Ok(());
+    }
+    ensure_closed(steam_dir)?;
+    for shortcuts in changed {
+        shortcuts.save()?;
+        log::info!("Removed {exe:?} from {}", shortcuts.path.display());
+    }
     Ok(())
 }
 
+/// The app ID of `shortcut` in `user`'s library: the one it was given there, or the one Steam
+/// would compute for it
+pub fn nonsteam_app_id(steam_dir: &Path, user: &SteamUser, shortcut: &Shortcut) -> Result<u32> {
+    let shortcuts = Shortcuts::load(&user.config_dir(steam_dir).join("shortcuts.vdf"))?;
+    Ok(shortcuts
+        .app_id(&shortcut.exe)
+        .unwrap_or_else(|| shortcut.app_id()))
+}
+
+/// The app IDs of the shortcut to `exe` in every Steam user's library
+pub fn nonsteam_app_ids(steam_dir: &Path, exe: &Path) -> Result<Vec<u32>> {
+    let mut app_ids = Vec::new();
+    for user in users(steam_dir)? {
+        let shortcuts = Shortcuts::load(&user.config_dir(steam_dir).join("shortcuts.vdf"))?;
+        app_ids.extend(shortcuts.app_id(exe));
+    }
+    Ok(app_ids)
+}
+
+/// Assign the controller template to the game and/or its shortcut for `users`, recording each
+/// config's previous assignments in the manifest so uninstall can put them back
 pub fn set_controller_config(
-    steam_dir: &steamlocate::SteamDir,
-    app_id: u32,
-    steam_shortcut: bool,
+    steam_dir: &Path,
+    users: &[SteamUser],
+    edition: &Edition,
+    shortcut: Option<&Shortcut>,
 ) -> Result<()> {
-    let (shortcut_id, is_gog) = match app_id {
-        39140 => ("(2013)", false),
-        3837340 => ("(2026)", false),
-        1698970154 => ("(gog)", true),
-        _ => ("(unknown)", false),
-    };
+    let app_id = edition.app_id.to_string();
+    let is_gog = edition.store == Store::Gog;
     let template = "controller_neptune_gamepad+mouse+click.vdf";
-    let config_glob = steam_dir
-        .path()
-        .join("steamapps/common/Steam Controller Configs/*/config/configset_controller_neptune.vdf")
-        .to_string_lossy()
-        .to_string();
-
-    let mut entries = Vec::new();
-    if !is_gog {
-        entries.push(format!(
-            r#"	"{app_id}"
-	{{
-		"template"		"{template}"
-	}}"#
-        ));
-    }
-    if steam_shortcut {
-        entries.push(format!(
-            r#"	"launch 7th heaven {shortcut_id}"
-	{{
-		"template"		"{template}"
-	}}"#
-        ));
-    }
-    let new_entries = entries.join("\n");
-
-    // Remove any existing entries for this app ID or the 7th Heaven shortcut
-    let remove_app_re = Regex::new(&format!(r#"[ \t]*"{app_id}"[^\{{]*\{{[^\}}]*\}}[ \t]*"#))?;
-    let escaped_id = regex::escape(shortcut_id);
-    let remove_7h_re = Regex::new(&format!(
-        r#"[ \t]*"launch 7th heaven {escaped_id}"[^\{{]*\{{[^\}}]*\}}[ \t]*"#
-    ))?;
-    let blank_lines_re = Regex::new(r"\n([ \t]*\n)+")?;
-
-    let inject_re = Regex::new(r#""controller_config"\s*\{"#)?;
-    let replacement = format!("\"controller_config\"\n{{\n{new_entries}");
-
-    for path in glob::glob(&config_glob)
-        .context("Invalid glob pattern")?
-        .flatten()
-    {
-        let content =
-            std::fs::read_to_string(&path).with_context(|| format!("Couldn't read {:?}", path))?;
-
-        let content = remove_app_re.replace_all(&content, "").to_string();
-        let content = remove_7h_re.replace_all(&content, "").to_string();
-        let content = blank_lines_re.replace_all(&content, "\n").to_string();
-        let content = inject_re.replace(&content, &replacement).to_string();
-        std::fs::write(&path, content.as_bytes())
-            .with_context(|| format!("Couldn't write to {:?}", path))?;
 
+    for user in users {
+        let mut keys = Vec::new();
+        if !is_gog {
+            keys.push(app_id.clone());
+        }
+        // Older versions keyed the shortcut by its lowercased name
+        let mut remove = vec![app_id.clone()];
+        if let Some(shortcut) = shortcut {
+            let shortcut_id = nonsteam_app_id(steam_dir, user, shortcut)?;
+            keys.push(shortcuts::game_id(shortcut_id).to_string());
+            remove.push(shortcut.app_name.to_lowercase());
+        }
+        remove.extend(keys.iter().cloned());
+
+        // Remove any existing entries for this app ID or the mod loader shortcut
+        let path = user.controller_config(steam_dir);
+        let mut config = load_controller_config(&path)?;
+        let mut previous = BTreeMap::new();
+        for key in remove {
+            let assigned = config.get(&["controller_config", &key, "template"]);
+            let assigned = assigned.map(String::from);
+            config.remove(&["controller_config", &key])?;
+            previous.entry(key).or_insert(assigned);
+        }
+        for key in &keys {
+            config.set(&["controller_config", key, "template"], template)?;
+        }
+        config.save()?;
         log::info!("Patched controller config: {:?}", path);
+        manifest::record(Change::ControllerConfig { path, previous });
     }
+    Ok(())
+}
 
+/// Put back the template assignments `previous` had in the controller config at `path`, dropping
+/// the keys that had none
+pub fn restore_controller_config(
+    path: &Path,
+    previous: &BTreeMap<String, Option<String>>,
+) -> Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    let mut config = Vdf::load(path)?;
+    for (key, template) in previous {
+        config.remove(&["controller_config", key])?;
+        if let Some(template) = template {
+            config.set(&["controller_config", key, "template"], template)?;
+        }
+    }
+    config.save()?;
+    log::info!("Restored controller config: {:?}", path);
     Ok(())
 }
+
+/// The controller config at `path`, or an empty one if the account doesn't have one yet
+fn load_controller_config(path: &Path) -> Result<Vdf> {
+    if path.is_file() {
+        return Vdf::load(path);
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Couldn't create {}", parent.display()))?;
+    }
+    Vdf::parse(path, "\"controller_config\"\n{\n}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editions;
+
+    fn user(id: u32) -> SteamUser {
+        SteamUser {
+            id,
+            account_name: None,
+            persona_name: None,
+            most_recent: false,
+        }
+    }
+
+    /// Two accounts that logged in, the second the one Steam uses by default
+    const LOGIN_USERS: &str = r#""users"
+{
+	"76561197960287930"
+	{
+		"AccountName"		"cloud"
+		"PersonaName"		"Cloud Strife"
+		"RememberPassword"		"1"
+		"MostRecent"		"0"
+		"Timestamp"		"1700000000"
+	}
+	"76561197960265729"
+	{
+		"AccountName"		"tifa"
+		"PersonaName"		""
+		"MostRecent"		"1"
+		"Timestamp"		"1700000100"
+	}
+	"76561197960265728999999"
+	{
+		"AccountName"		"broken"
+	}
+}
+"#;
+
+    /// A Steam directory with `loginusers.vdf` and the `userdata/` folders named `ids`
+    fn steam_dir(login_users: Option<&str>, ids: &[&str]) -> tempfile::TempDir {
+        let steam = tempfile::tempdir().unwrap();
+        if let Some(login_users) = login_users {
+            std::fs::create_dir_all(steam.path().join("config")).unwrap();
+            std::fs::write(steam.path().join("config/loginusers.vdf"), login_users).unwrap();
+        }
+        for id in ids {
+            std::fs::create_dir_all(steam.path().join("userdata").join(id).join("config")).unwrap();
+        }
+        steam
+    }
+
+    #[test]
+    fn users_from_login_users_and_userdata() {
+        // 22202 logged in but has no userdata yet, 424242 only has userdata, and 0 is Steam's own
+        let steam = steam_dir(Some(LOGIN_USERS), &["0", "1", "424242"]);
+        std::fs::write(steam.path().join("userdata/31337"), "").unwrap();
+
+        let users = users(steam.path()).unwrap();
+        assert_eq!(
+            users,
+            [
+                SteamUser {
+                    id: 1,
+                    account_name: Some("tifa".to_string()),
+                    persona_name: None,
+                    most_recent: true,
+                },
+                SteamUser {
+                    id: 22202,
+                    account_name: Some("cloud".to_string()),
+                    persona_name: Some("Cloud Strife".to_string()),
+                    most_recent: false,
+                },
+                user(424242),
+            ]
+        );
+        let names: Vec<_> = users.iter().map(ToString::to_string).collect();
+        assert_eq!(names, ["tifa (1)", "Cloud Strife (cloud)", "424242"]);
+        let answers: Vec<_> = users.iter().map(SteamUser::answer_id).collect();
+        assert_eq!(answers, ["tifa", "cloud", "424242"]);
+        assert_eq!(
+            users[1].config_dir(steam.path()),
+            steam.path().join("userdata/22202/config")
+        );
+    }
+
+    #[test]
+    fn users_without_login_users() {
+        let steam = steam_dir(None, &["1001"]);
+        assert_eq!(users(steam.path()).unwrap(), [user(1001)]);
+    }
+
+    #[test]
+    fn single_user_is_selected_without_asking() {
+        let steam = steam_dir(None, &["1001"]);
+        assert_eq!(select_users(steam.path()).unwrap(), [user(1001)]);
+
+        let steam = steam_dir(None, &["0"]);
+        let error = select_users(steam.path()).unwrap_err();
+        assert!(error.to_string().starts_with("No Steam users found"));
+    }
+
+    #[test]
+    fn controller_config_is_restored() {
+        let steam = tempfile::tempdir().unwrap();
+        let edition = editions::by_app_id(39140).unwrap();
+        let users = [user(1001)];
+        let untouched = user(1002);
+        let path = users[0].controller_config(steam.path());
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let before = "\"controller_config\"\n{\n\t\"39140\"\n\t{\n\t\t\"template\"\t\t\"mine.vdf\"\n\t}\n}\n";
+        std::fs::write(&path, before).unwrap();
+
+        let manifest = {
+            let _lock = manifest::recording_lock();
+            manifest::begin();
+            let result = set_controller_config(steam.path(), &users, edition, None);
+            let manifest = manifest::finish();
+            result.unwrap();
+            manifest
+        };
+        let config = Vdf::load(&path).unwrap();
+        assert_eq!(
+            config.get(&["controller_config", "39140", "template"]),
+            Some("controller_neptune_gamepad+mouse+click.vdf")
+        );
+        assert!(!untouched.controller_config(steam.path()).exists());
+
+        let change = Change::ControllerConfig {
+            path: path.clone(),
+            previous: [("39140".to_string(), Some("mine.vdf".to_string()))].into(),
+        };
+        assert_eq!(manifest.changes.len(), 1);
+        assert_eq!(manifest.changes[0], change);
+        change.undo().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), before);
+    }
+
+    #[test]
+    fn controller_config_without_previous_is_removed() {
+        let steam = tempfile::tempdir().unwrap();
+        let path = user(1001).controller_config(steam.path());
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &path,
+            "\"controller_config\"\n{\n\t\"39140\"\n\t{\n\t\t\"template\"\t\t\"ours.vdf\"\n\t}\n}\n",
+        )
+        .unwrap();
+
+        restore_controller_config(&path, &[("39140".to_string(), None)].into()).unwrap();
+        let config = Vdf::load(&path).unwrap();
+        assert!(config.keys(&["controller_config"]).is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 use anyhow::{bail, Context, Result};
-use dialoguer::theme::ColorfulTheme;
 use std::{fs, path::PathBuf};
 
+use crate::answers;
+use crate::config_handler;
 use crate::gamelib_helper::{Runner, Runtime};
 
 pub fn select_version(runners: &[Runner]) -> Result<Runner> {
@@ -25,12 +26,31 @@ pub fn select_version(runners: &[Runner]) -> Result<Runner> {
         .chain(runners.iter().map(|r| r.pretty_name.as_str()))
         .collect();
 
-    let selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select a Proton version to use")
-        .default(0)
-        .items(&choices)
-        .interact()
-        .context("Proton selection failed")?;
+    let ids: Vec<&str> = std::iter::once("automatic")
+        .chain(runners.iter().map(|r| r.name.as_str()))
+        .collect();
+
+    // The user's preferred Proton is the default choice, and is taken without asking when
+    // running non-interactively
+    let preferred = config_handler::user()
+        .runner
+        .as_deref()
+        .and_then(|name| runners.iter().position(|r| r.name == name));
+    if let Some(preferred) = preferred {
+        let answers = answers::get();
+        if !answers.is_interactive() && answers.get_str("proton")?.is_none() {
+            return Ok(runners[preferred].clone());
+        }
+    }
+
+    let selection = answers::select(
+        "proton",
+        "Select a Proton version to use",
+        &choices,
+        &ids,
+        preferred.map_or(0, |i| i + 1),
+    )
+    .context("Proton selection failed")?;
 
     if selection == 0 {
         Ok(find_highest_version(&runners)
@@ -151,9 +171,7 @@ pub fn find_all_versions(steam_dir: steamlocate::SteamDir) -> Result<Vec<Runner>
     }
 
     proton_versions.extend(
-        find_custom_versions(steam_dir.clone())
-            .context("Failed to find custom Proton versions")?
-            .into_iter(),
+        find_custom_versions(steam_dir.clone()).context("Failed to find custom Proton versions")?,
     );
 
     if proton_versions.is_empty() {
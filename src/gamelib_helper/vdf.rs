@@ -0,0 +1,514 @@
+use crate::manifest::{self, FileKind};
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// A text KeyValues (VDF) file, e.g. Steam's `config.vdf`, kept with its whitespace and comments
+/// so edits only touch the keys they're about
+#[derive(Debug, Clone)]
+pub struct Vdf {
+    pub path: PathBuf,
+    pairs: Vec<Pair>,
+    /// Whitespace and comments after the last pair
+    trailing: String,
+}
+
+#[derive(Debug, Clone)]
+struct Pair {
+    /// Whitespace and comments before the key
+    leading: String,
+    key: Token,
+    /// Whitespace and comments between the key and its value
+    between: String,
+    value: Value,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Str(Token),
+    Obj {
+        pairs: Vec<Pair>,
+        /// Whitespace and comments before the closing brace
+        closing: String,
+    },
+}
+
+/// A string as written in the file, quoted or not
+#[derive(Debug, Clone)]
+struct Token {
+    raw: String,
+    text: String,
+}
+
+impl Token {
+    fn new(text: &str) -> Self {
+        let escaped = text.replace('\\', "\\\\").replace('"', "\\\"");
+        Token {
+            raw: format!("\"{escaped}\""),
+            text: text.to_string(),
+        }
+    }
+}
+
+impl Vdf {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        Self::parse(path, &text)
+    }
+
+    pub fn parse(path: &Path, text: &str) -> Result<Self> {
+        // Refuse anything Steam's own format doesn't allow before touching it
+        keyvalues_parser::parse(text.trim_start_matches(BOM))
+            .with_context(|| format!("Couldn't parse {}", path.display()))?;
+        let mut parser = Parser { text, pos: 0 };
+        let (pairs, trailing) = parser
+            .pairs(false)
+            .with_context(|| format!("Couldn't parse {}", path.display()))?;
+        Ok(Vdf {
+            path: path.to_path_buf(),
+            pairs,
+            trailing,
+        })
+    }
+
+    /// The string at `path`, matching keys case-insensitively like Steam does
+    pub fn get(&self, path: &[&str]) -> Option<&str> {
+        match &find(&self.pairs, path)?.value {
+            Value::Str(token) => Some(&token.text),
+            Value::Obj { .. } => None,
+        }
+    }
+
+    /// Whether there's a string or object at `path`
+    pub fn contains(&self, path: &[&str]) -> bool {
+        find(&self.pairs, path).is_some()
+    }
+
+    /// Set the string at `path`, creating the objects leading to it
+    pub fn set(&mut self, path: &[&str], value: &str) -> Result<()> {
+        let Some((key, parents)) = path.split_last() else {
+            bail!("Empty key path");
+        };
+        let (pairs, closing, depth) = self.object_mut(parents)?;
+        match pairs
+            .iter_mut()
+            .find(|pair| pair.key.text.eq_ignore_ascii_case(key))
+        {
+            Some(Pair {
+                value: Value::Str(token),
+                ..
+            }) => *token = Token::new(value),
+            Some(_) => bail!("`{}` is an object, not a string", path.join("/")),
+            None => {
+                let leading = leading_for(pairs, closing, depth);
+                pairs.push(Pair {
+                    leading,
+                    key: Token::new(key),
+                    between: "\t\t".to_string(),
+                    value: Value::Str(Token::new(value)),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the string or object at `path`, returning whether it was there
+    pub fn remove(&mut self, path: &[&str]) -> Result<bool> {
+        let Some((key, parents)) = path.split_last() else {
+            bail!("Empty key path");
+        };
+        if parents.is_empty() {
+            return Ok(remove_pair(&mut self.pairs, key));
+        }
+        match find_mut(&mut self.pairs, parents).map(|pair| &mut pair.value) {
+            Some(Value::Obj { pairs, .. }) => Ok(remove_pair(pairs, key)),
+            _ => Ok(false),
+        }
+    }
+
+    /// The keys of the object at `path`, in file order
+    pub fn keys(&self, path: &[&str]) -> Vec<&str> {
+        let pairs = match path.is_empty() {
+            true => &self.pairs,
+            false => match find(&self.pairs, path).map(|pair| &pair.value) {
+                Some(Value::Obj { pairs, .. }) => pairs,
+                _ => return Vec::new(),
+            },
+        };
+        pairs.iter().map(|pair| pair.key.text.as_str()).collect()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        manifest::write(
+            &self.path,
+            self.to_string().as_bytes(),
+            FileKind::SteamConfig,
+        )
+    }
+
+    /// The pairs of the object at `path` and the trivia before its closing brace, creating it
+    /// and its parents if needed
+    fn object_mut(&mut self, path: &[&str]) -> Result<(&mut Vec<Pair>, &mut String, usize)> {
+        let mut pairs = &mut self.pairs;
+        let mut closing = &mut self.trailing;
+        for (depth, key) in path.iter().enumerate() {
+            let index = match pairs
+                .iter()
+                .position(|pair| pair.key.text.eq_ignore_ascii_case(key))
+            {
+                Some(index) => index,
+                None => {
+                    let leading = leading_for(pairs, closing, depth);
+                    let indent = leading.rsplit('\n').next().unwrap_or_default().to_string();
+                    pairs.push(Pair {
+                        leading,
+                        key: Token::new(key),
+                        between: format!("\n{indent}"),
+                        value: Value::Obj {
+                            pairs: Vec::new(),
+                            closing: format!("\n{indent}"),
+                        },
+                    });
+                    pairs.len() - 1
+                }
+            };
+            match &mut pairs[index].value {
+                Value::Obj {
+                    pairs: children,
+                    closing: children_closing,
+                } => {
+                    pairs = children;
+                    closing = children_closing;
+                }
+                Value::Str(_) => {
+                    let key = path[..=depth].join("/");
+                    bail!("`{key}` is a string, not an object")
+                }
+            }
+        }
+        Ok((pairs, closing, path.len()))
+    }
+}
+
+impl std::fmt::Display for Vdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for pair in &self.pairs {
+            write_pair(f, pair)?;
+        }
+        f.write_str(&self.trailing)
+    }
+}
+
+fn write_pair(f: &mut std::fmt::Formatter<'_>, pair: &Pair) -> std::fmt::Result {
+    f.write_str(&pair.leading)?;
+    f.write_str(&pair.key.raw)?;
+    f.write_str(&pair.between)?;
+    match &pair.value {
+        Value::Str(token) => f.write_str(&token.raw),
+        Value::Obj { pairs, closing } => {
+            f.write_str("{")?;
+            for pair in pairs {
+                write_pair(f, pair)?;
+            }
+            f.write_str(closing)?;
+            f.write_str("}")
+        }
+    }
+}
+
+fn find<'a>(pairs: &'a [Pair], path: &[&str]) -> Option<&'a Pair> {
+    let (key, rest) = path.split_first()?;
+    let pair = pairs
+        .iter()
+        .find(|pair| pair.key.text.eq_ignore_ascii_case(key))?;
+    match (rest.is_empty(), &pair.value) {
+        (true, _) => Some(pair),
+        (false, Value::Obj { pairs, .. }) => find(pairs, rest),
+        (false, Value::Str(_)) => None,
+    }
+}
+
+fn find_mut<'a>(pairs: &'a mut [Pair], path: &[&str]) -> Option<&'a mut Pair> {
+    let (key, rest) = path.split_first()?;
+    let pair = pairs
+        .iter_mut()
+        .find(|pair| pair.key.text.eq_ignore_ascii_case(key))?;
+    match rest.is_empty() {
+        true => Some(pair),
+        false => match &mut pair.value {
+            Value::Obj { pairs, .. } => find_mut(pairs, rest),
+            Value::Str(_) => None,
+        },
+    }
+}
+
+fn remove_pair(pairs: &mut Vec<Pair>, key: &str) -> bool {
+    let before = pairs.len();
+    pairs.retain(|pair| !pair.key.text.eq_ignore_ascii_case(key));
+    pairs.len() != before
+}
+
+/// Trivia for a new pair appended to `pairs`: the same indent as its siblings, or one tab
+/// deeper than the object's parent when it has none
+fn leading_for(pairs: &[Pair], closing: &mut String, depth: usize) -> String {
+    if let Some(last) = pairs.last() {
+        if let Some(newline) = last.leading.rfind('\n') {
+            return last.leading[newline..].to_string();
+        }
+    }
+    // An empty object written as `{}` needs its closing brace on a line of its own
+    if depth > 0 && !closing.contains('\n') {
+        *closing = format!("\n{}", "\t".repeat(depth - 1));
+    }
+    match pairs.is_empty() && depth == 0 {
+        true => String::new(),
+        false => format!("\n{}", "\t".repeat(depth)),
+    }
+}
+
+/// Steam writes some files, e.g. exported controller configs, with a byte order mark
+const BOM: char = '\u{feff}';
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Pairs up to the closing brace of an object, or the end of the file
+    fn pairs(&mut self, in_object: bool) -> Result<(Vec<Pair>, String)> {
+        let mut pairs = Vec::new();
+        loop {
+            let leading = self.trivia();
+            match self.peek() {
+                None if in_object => bail!("Missing `}}` at the end of the file"),
+                None => return Ok((pairs, leading)),
+                Some('}') if in_object => {
+                    self.pos += 1;
+                    return Ok((pairs, leading));
+                }
+                Some('{' | '}') => bail!("Unexpected brace at byte {}", self.pos),
+                Some(_) => {}
+            }
+            let key = self.token()?;
+            let between = self.trivia();
+            let value = match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    let (pairs, closing) = self.pairs(true)?;
+                    Value::Obj { pairs, closing }
+                }
+                Some(_) => Value::Str(self.token()?),
+                None => bail!("Missing a value for `{}`", key.text),
+            };
+            pairs.push(Pair {
+                leading,
+                key,
+                between,
+                value,
+            });
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    /// Whitespace, `//` comments, `[$CONDITION]`s and a byte order mark at the start
+    fn trivia(&mut self) -> String {
+        let start = self.pos;
+        loop {
+            let rest = &self.text[self.pos..];
+            if self.pos == 0 && rest.starts_with(BOM) {
+                self.pos += BOM.len_utf8();
+            } else if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if rest.starts_with('[') {
+                self.pos += rest.find(']').map_or(rest.len(), |end| end + 1);
+            } else if let Some(c) = rest.chars().next().filter(|c| c.is_whitespace()) {
+                self.pos += c.len_utf8();
+            } else {
+                return self.text[start..self.pos].to_string();
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<Token> {
+        let start = self.pos;
+        let rest = &self.text[self.pos..];
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut text = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        self.pos += i + 2;
+                        return Ok(Token {
+                            raw: self.text[start..self.pos].to_string(),
+                            text,
+                        });
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, escaped)) => text.push(escaped),
+                        None => break,
+                    },
+                    c => text.push(c),
+                }
+            }
+            bail!("Unterminated string at byte {start}");
+        }
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '{' | '}'))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Ok(Token {
+            raw: rest[..len].to_string(),
+            text: rest[..len].to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource_handler::CONTROLLER_PROFILE;
+
+    /// An app manifest written by Steam, from steamlocate's test assets (MIT)
+    const APP_MANIFEST: &str = include_str!("testdata/appmanifest_230410.acf");
+
+    fn parse(text: &str) -> Vdf {
+        Vdf::parse(Path::new("test.vdf"), text).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in [APP_MANIFEST, CONTROLLER_PROFILE] {
+            assert_eq!(parse(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn set_only_touches_its_key() {
+        let mut vdf = parse(APP_MANIFEST);
+        vdf.set(&["AppState", "StateFlags"], "6").unwrap();
+        assert_eq!(vdf.get(&["appstate", "stateflags"]), Some("6"));
+        let expected = APP_MANIFEST.replace("\"StateFlags\"\t\t\"4\"", "\"StateFlags\"\t\t\"6\"");
+        assert_eq!(vdf.to_string(), expected);
+    }
+
+    /// The part of Steam's `config/config.vdf` that `steam_game::set_compat_tool` edits
+    const CONFIG: &str = "\"InstallConfigStore\"
+{
+\t\"Software\"
+\t{
+\t\t\"Valve\"
+\t\t{
+\t\t\t\"Steam\"
+\t\t\t{
+\t\t\t\t\"AutoUpdateWindowEnabled\"\t\t\"0\"
+\t\t\t\t\"CompatToolMapping\"
+\t\t\t\t{
+\t\t\t\t\t\"0\"
+\t\t\t\t\t{
+\t\t\t\t\t\t\"name\"\t\t\"proton_experimental\"
+\t\t\t\t\t\t\"config\"\t\t\"\"
+\t\t\t\t\t\t\"priority\"\t\t\"75\"
+\t\t\t\t\t}
+\t\t\t\t}
+\t\t\t}
+\t\t}
+\t}
+}
+";
+
+    /// Set and then drop a mapping the way `set_compat_tool` does
+    fn map_and_unmap(text: &str, parent: &[&str]) -> (String, String) {
+        let mut vdf = parse(text);
+        let mapping = [parent, &["3141592653"]].concat();
+        for (key, value) in [
+            ("name", "GE-Proton9-20"),
+            ("config", ""),
+            ("priority", "250"),
+        ] {
+            vdf.set(&[&mapping[..], &[key]].concat(), value).unwrap();
+        }
+        let mapped = vdf.to_string();
+        assert_eq!(
+            vdf.get(&[&mapping[..], &["name"]].concat()),
+            Some("GE-Proton9-20")
+        );
+        assert!(vdf.remove(&mapping).unwrap());
+        assert!(!vdf.remove(&mapping).unwrap());
+        (mapped, vdf.to_string())
+    }
+
+    #[test]
+    fn nested_compat_tool_mapping() {
+        let parent = [
+            "InstallConfigStore",
+            "Software",
+            "Valve",
+            "Steam",
+            "CompatToolMapping",
+        ];
+        let (mapped, unmapped) = map_and_unmap(CONFIG, &parent);
+        let expected = CONFIG.replace(
+            "\t\t\t\t\t}\n\t\t\t\t}\n",
+            "\t\t\t\t\t}
+\t\t\t\t\t\"3141592653\"
+\t\t\t\t\t{
+\t\t\t\t\t\t\"name\"\t\t\"GE-Proton9-20\"
+\t\t\t\t\t\t\"config\"\t\t\"\"
+\t\t\t\t\t\t\"priority\"\t\t\"250\"
+\t\t\t\t\t}
+\t\t\t\t}
+",
+        );
+        assert_eq!(mapped, expected);
+        assert_eq!(parse(&mapped).keys(&parent), ["0", "3141592653"]);
+        assert_eq!(unmapped, CONFIG);
+    }
+
+    #[test]
+    fn compat_tool_mapping_is_created_when_missing() {
+        let config = "\"InstallConfigStore\"\n{\n\t\"Software\"\n\t{\n\t}\n}\n";
+        let parent = [
+            "InstallConfigStore",
+            "Software",
+            "Valve",
+            "Steam",
+            "CompatToolMapping",
+        ];
+        let (mapped, unmapped) = map_and_unmap(config, &parent);
+        assert_eq!(
+            mapped,
+            "\"InstallConfigStore\"
+{
+\t\"Software\"
+\t{
+\t\t\"Valve\"
+\t\t{
+\t\t\t\"Steam\"
+\t\t\t{
+\t\t\t\t\"CompatToolMapping\"
+\t\t\t\t{
+\t\t\t\t\t\"3141592653\"
+\t\t\t\t\t{
+\t\t\t\t\t\t\"name\"\t\t\"GE-Proton9-20\"
+\t\t\t\t\t\t\"config\"\t\t\"\"
+\t\t\t\t\t\t\"priority\"\t\t\"250\"
+\t\t\t\t\t}
+\t\t\t\t}
+\t\t\t}
+\t\t}
+\t}
+}
+"
+        );
+        // The objects created above the mapping stay, now empty
+        assert!(parse(&unmapped).contains(&parent));
+        assert!(parse(&unmapped).keys(&parent).is_empty());
+    }
+}
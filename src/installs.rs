@@ -0,0 +1,166 @@
+use crate::config_handler::CONFIG_NAME;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const REGISTRY_NAME: &str = "installs.toml";
+
+/// Install paths MateriaForge has installed to, kept in `$XDG_DATA_HOME/MateriaForge`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Registry {
+    #[serde(default)]
+    installs: Vec<PathBuf>,
+}
+
+fn registry_path() -> Result<PathBuf> {
+    Ok(xdg::BaseDirectories::with_prefix("MateriaForge")
+        .get_data_home()
+        .context("Couldn't get data_home")?
+        .join(REGISTRY_NAME))
+}
+
+fn load() -> Result<Registry> {
+    let path = registry_path()?;
+    if !path.is_file() {
+        return Ok(Registry::default());
+    }
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    toml::from_str(&contents).with_context(|| format!("Couldn't parse {}", path.display()))
+}
+
+fn save(registry: &Registry) -> Result<()> {
+    let path = registry_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, toml::to_string(registry)?)
+        .with_context(|| format!("Couldn't write {}", path.display()))
+}
+
+/// Remember an install so commands can find it later
+pub fn register(install_path: &Path) -> Result<()> {
+    let install_path = install_path
+        .canonicalize()
+        .unwrap_or_else(|_| install_path.to_path_buf());
+    let mut registry = load()?;
+    if !registry.installs.contains(&install_path) {
+        registry.installs.push(install_path);
+        save(&registry)?;
+    }
+    Ok(())
+}
+
+pub fn unregister(install_path: &Path) -> Result<()> {
+    let install_path = install_path
+        .canonicalize()
+        .unwrap_or_else(|_| install_path.to_path_buf());
+    let mut registry = load()?;
+    registry.installs.retain(|path| *path != install_path);
+    save(&registry)
+}
+
+/// Every install that still exists: registered ones, and ones from before the registry found
+/// through the `Path=` of their app launcher shortcut
+pub fn find() -> Result<Vec<PathBuf>> {
+    let applications_dir = xdg::BaseDirectories::new()
+        .get_data_home()
+        .context("Couldn't get xdg_data_home")?
+        .join("applications");
+    Ok(find_in(load()?.installs, &applications_dir))
+}
+
+/// The installs among `registered` and the `Path=` of the `.desktop` files in
+/// `applications_dir`, each listed once however its path is spelled
+fn find_in(registered: Vec<PathBuf>, applications_dir: &Path) -> Vec<PathBuf> {
+    let mut candidates = registered;
+    for entry in std::fs::read_dir(applications_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        if entry.path().extension().is_none_or(|ext| ext != "desktop") {
+            continue;
+        }
+        let Ok(contents) = std::fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        if let Some(path) = contents.lines().find_map(|line| line.strip_prefix("Path=")) {
+            candidates.push(PathBuf::from(path));
+        }
+    }
+
+    let mut found = Vec::new();
+    for path in candidates {
+        if !path.join(CONFIG_NAME).is_file() {
+            continue;
+        }
+        let path = path.canonicalize().unwrap_or(path);
+        if !found.contains(&path) {
+            found.push(path);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desktop_file(applications: &Path, name: &str, path: &str) {
+        let contents = format!("[Desktop Entry]\nName={name}\nExec=launcher\nPath={path}\n");
+        std::fs::write(applications.join(format!("{name}.desktop")), contents).unwrap();
+    }
+
+    #[test]
+    fn find_lists_each_install_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let (seventh_heaven, junction_viii) = (dir.join("7th Heaven"), dir.join("Junction VIII"));
+        for install in [&seventh_heaven, &junction_viii, &dir.join("Removed")] {
+            std::fs::create_dir_all(install).unwrap();
+        }
+        std::fs::write(seventh_heaven.join(CONFIG_NAME), "").unwrap();
+        std::fs::write(junction_viii.join(CONFIG_NAME), "").unwrap();
+        std::os::unix::fs::symlink(&seventh_heaven, dir.join("Games")).unwrap();
+
+        let applications = dir.join("applications");
+        std::fs::create_dir_all(&applications).unwrap();
+        // The same install through a trailing slash and a symlink, one without a config and
+        // one that isn't an app launcher shortcut
+        desktop_file(
+            &applications,
+            "7th Heaven",
+            &format!("{}/", seventh_heaven.display()),
+        );
+        desktop_file(
+            &applications,
+            "7th Heaven (link)",
+            &dir.join("Games").to_string_lossy(),
+        );
+        desktop_file(
+            &applications,
+            "Removed",
+            &dir.join("Removed").to_string_lossy(),
+        );
+        desktop_file(
+            &applications,
+            "Junction VIII",
+            &junction_viii.to_string_lossy(),
+        );
+        std::fs::rename(
+            applications.join("Junction VIII.desktop"),
+            applications.join("Junction VIII.txt"),
+        )
+        .unwrap();
+
+        let found = find_in(vec![seventh_heaven.clone()], &applications);
+        assert_eq!(found, std::slice::from_ref(&seventh_heaven));
+
+        let found = find_in(
+            vec![seventh_heaven.clone(), junction_viii.clone()],
+            &applications,
+        );
+        assert_eq!(found, [seventh_heaven, junction_viii]);
+    }
+}
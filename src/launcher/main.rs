@@ -1,15 +1,14 @@
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 use anyhow::{bail, Context, Result};
-use lib_game_detector::data::SupportedLaunchers;
-use std::{env, path::Path};
+use std::env;
 
-use materia_forge::gamelib_helper::{Game, PrefixRunner};
-use materia_forge::{config_handler, gamelib_helper, logging};
+use materia_forge::config_handler::{self, Config};
+use materia_forge::gamelib_helper::PrefixedGame;
+use materia_forge::mod_loader;
+use materia_forge::{gamelib_helper, logging};
 
-static FF7_GOG_APPID: u32 = 1698970154;
-
-fn run_exe<G: Game + PrefixRunner>(game: &G, exe: std::path::PathBuf) -> Result<()> {
+fn run_exe(game: &dyn PrefixedGame, exe: std::path::PathBuf, config: &Config) -> Result<()> {
     if let Some(runner) = game.runner() {
         log::info!("Found runner: {}", runner.name);
     } else {
@@ -18,8 +17,10 @@ fn run_exe<G: Game + PrefixRunner>(game: &G, exe: std::path::PathBuf) -> Result<
 
     let cli_args: Vec<String> = env::args().skip(1).collect();
     let args = if cli_args.is_empty() {
-        config_handler::read_value("launch_args")
-            .unwrap_or_else(|_| "".to_string())
+        config
+            .launch_args
+            .as_deref()
+            .unwrap_or_default()
             .split_whitespace()
             .map(|s| s.to_string())
             .collect()
@@ -33,8 +34,12 @@ fn run_exe<G: Game + PrefixRunner>(game: &G, exe: std::path::PathBuf) -> Result<
         log::info!("Launch arguments: {:?}", args);
     }
 
-    game.run_in_prefix(exe, Some(args))
-        .context("Failed to launch 7th Heaven")?;
+    let exe_name = exe
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    game.run_in_prefix(exe, Some(args), &config.env)
+        .with_context(|| format!("Failed to launch {exe_name}"))?;
     Ok(())
 }
 
@@ -46,45 +51,17 @@ fn main() -> Result<()> {
     let launcher_dir = launcher_bin
         .parent()
         .context("Failed to get binary directory")?;
-    let seventh_heaven_exe = launcher_dir.join("7th Heaven.exe");
+    let config = config_handler::current()?;
+    let loader = mod_loader::from_id(&config.loader)
+        .with_context(|| format!("Unknown loader '{}' in config", config.loader))?;
+    let loader_exe = launcher_dir.join(loader.exe_name());
 
-    if !seventh_heaven_exe.exists() {
-        bail!("Couldn't find '7th Heaven.exe'!");
+    if !loader_exe.exists() {
+        bail!("Couldn't find '{}'!", loader.exe_name());
     }
 
-    let install_type = config_handler::read_value("type")
-        .unwrap_or_else(|_| "steam".to_string())
-        .to_lowercase();
-
-    match install_type.as_str() {
-        "gog" => {
-            let g = lib_game_detector::get_detector()
-                .get_all_detected_games_from_specific_launcher(SupportedLaunchers::HeroicGamesGOG);
-            let heroic_game = g
-                .iter()
-                .flatten()
-                .find(|game| game.title.to_lowercase().contains("final fantasy vii"))
-                .unwrap();
-            let game = gamelib_helper::gog_game::get_game(FF7_GOG_APPID, &heroic_game)
-                .context("Configured type=gog, but GOG game was not found")?;
-            run_exe(&game, seventh_heaven_exe)?;
-        }
-        _ => {
-            let steam_dir_str = config_handler::read_value("steam_dir")
-                .context("Configured type=steam, but steam_dir is missing in TOML")?;
-            let steam_dir = steamlocate::SteamDir::from_dir(Path::new(&steam_dir_str))?;
-            log::info!("Steam path: {}", steam_dir.path().display());
-
-            let app_id = config_handler::read_value("app_id")
-                .context("Configured type=steam, but app_id is missing in TOML")?;
-            log::info!("App ID: {}", app_id);
-
-            let mut game = gamelib_helper::steam_game::get_game(app_id.parse()?, steam_dir.clone())
-                .context(format!("Couldn't find {} in Steam library", app_id))?;
-            game.runner = Some(gamelib_helper::steam_game::get_runner(&game)?);
-            run_exe(&game, seventh_heaven_exe)?;
-        }
-    }
+    let game = gamelib_helper::from_config(config, loader.as_ref())?;
+    run_exe(game.as_ref(), loader_exe, config)?;
 
     Ok(())
 }
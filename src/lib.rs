@@ -1,4 +1,15 @@
+pub mod answers;
 pub mod config_handler;
+pub mod doctor;
+pub mod downloader;
+pub mod editions;
 pub mod gamelib_helper;
+pub mod installs;
 pub mod logging;
+pub mod manifest;
+pub mod mod_loader;
+pub mod release_source;
 pub mod resource_handler;
+pub mod settings_xml;
+#[cfg(test)]
+mod test_server;
@@ -3,39 +3,310 @@
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 use anyhow::{bail, Context, Result};
+use clap::{ArgAction, Parser, Subcommand};
 use console::Style;
 use dialoguer::theme::ColorfulTheme;
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
 use lib_game_detector::{data::SupportedLaunchers, get_detector};
 use materia_forge::{
-    config_handler,
-    gamelib_helper::{self, gog_game, PrefixedGame, DEFAULT_WINEDEBUG},
-    logging, resource_handler,
+    answers::{self, Answers},
+    config_handler::{self, Channel, Config, ConfigFile, InstallType},
+    doctor::{self, Status},
+    downloader,
+    editions::{self, Drive, Edition, Store},
+    gamelib_helper::{
+        self, artwork, custom_game, gog_game, lutris_game, shortcuts::Shortcut,
+        steam_lib::SteamUser, PrefixedGame, DEFAULT_WINEDEBUG,
+    },
+    installs, logging,
+    manifest::{self, Change, FileKind, Manifest},
+    mod_loader::{self, ModLoader},
+    release_source::ReleaseSource,
+    resource_handler,
+    settings_xml::{self, Catalog, Settings},
 };
 use rfd::FileDialog;
 use std::{
-    collections::HashMap,
+    collections::BTreeSet,
     env,
     fmt::Write,
-    fs::File,
     path::{Path, PathBuf},
     sync::LazyLock,
     time::Duration,
 };
 
-const FF7_APPID: u32 = 39140;
-const FF7_2026_APPID: u32 = 3837340;
-const FF7_GOG_APPID: u32 = 1698970154;
+/// All-in-one installer for 7th Heaven and Junction VIII on Linux
+#[derive(Parser, Debug)]
+#[command(name = "MateriaForge", version = VERSION, disable_version_flag = true)]
+struct Cli {
+    /// Print version
+    #[arg(short = 'V', action = ArgAction::Version)]
+    print_version: (),
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Install pre-release (canary) versions of the mod loader and FFNx
+    #[arg(short, long, global = true)]
+    canary: bool,
+
+    /// Force detection of Steam Deck for controller config option
+    #[arg(short, long)]
+    deck: bool,
+
+    /// Never prompt; every decision must come from flags or the answer file
+    #[arg(long, global = true)]
+    non_interactive: bool,
+
+    /// User defaults file to use instead of ~/.config/MateriaForge/config.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// TOML file answering the installer's prompts
+    #[arg(long, value_name = "FILE", global = true)]
+    answers: Option<PathBuf>,
+
+    /// Game to mod when both are installed: ff7, ff8
+    #[arg(long, value_name = "GAME", global = true)]
+    game: Option<String>,
+
+    /// Store to use when the game is found in more than one: steam, heroic, lutris, or custom
+    /// to use a wine prefix set up outside of them
+    #[arg(long, value_name = "STORE")]
+    store: Option<String>,
+
+    /// Steam installation to use when more than one exists: native, flatpak
+    #[arg(long, value_name = "INSTALL")]
+    steam_install: Option<String>,
+
+    /// Steam account to add the shortcut and controller config for: its account name, or all
+    #[arg(long, value_name = "USER")]
+    steam_user: Option<String>,
+
+    /// Edition to use when several releases match: 2013, 2026, gog (FF7) or 2013, remastered (FF8)
+    #[arg(long, value_name = "EDITION")]
+    edition: Option<String>,
+
+    /// Proton version to use: automatic, or a runner name such as proton_9
+    #[arg(long, value_name = "RUNNER")]
+    proton: Option<String>,
+
+    /// Directory to install the mod loader into
+    #[arg(long, value_name = "DIR", global = true)]
+    install_path: Option<PathBuf>,
+
+    /// Game directory for --store custom
+    #[arg(long, value_name = "DIR")]
+    game_dir: Option<PathBuf>,
+
+    /// Wine prefix for --store custom
+    #[arg(long, value_name = "DIR")]
+    prefix: Option<PathBuf>,
+
+    /// Wine binary or GE-Proton `proton` script for --store custom
+    #[arg(long, value_name = "FILE")]
+    wine: Option<PathBuf>,
+
+    /// Install this release tag of the mod loader instead of the latest one
+    #[arg(long, value_name = "TAG", global = true)]
+    version: Option<String>,
+
+    /// Release API to download from, e.g. https://git.example.com/api/v1 for a Gitea or Forgejo mirror
+    #[arg(long, value_name = "URL", global = true)]
+    api_url: Option<String>,
+
+    /// Repo to download releases from, as owner/name
+    #[arg(long, value_name = "REPO", global = true)]
+    repo: Option<String>,
+
+    /// Regex matching the installer's asset name in a release
+    #[arg(long, value_name = "REGEX", global = true)]
+    asset_pattern: Option<String>,
+
+    /// Token sent to the release API, instead of the configured one or $GITHUB_TOKEN
+    #[arg(long, value_name = "TOKEN", global = true)]
+    token: Option<String>,
+
+    /// Install from a local installer instead of downloading one
+    #[arg(long, value_name = "FILE", global = true)]
+    installer: Option<PathBuf>,
+
+    /// Add a shortcut to the Desktop
+    #[arg(long, value_name = "BOOL")]
+    desktop_shortcut: Option<bool>,
+
+    /// Add a non-Steam game shortcut to Steam
+    #[arg(long, value_name = "BOOL")]
+    steam_shortcut: Option<bool>,
+
+    /// Folder with custom Steam artwork for the shortcut: grid, wide_grid, hero, logo and icon
+    /// as .png or .jpg
+    #[arg(long, value_name = "DIR")]
+    artwork: Option<PathBuf>,
+
+    /// Add a Steam Deck controller configuration
+    #[arg(long, value_name = "BOOL")]
+    controller_config: Option<bool>,
+
+    /// Close Steam if it's running when its shortcuts need changing
+    #[arg(long, value_name = "BOOL", global = true)]
+    close_steam: Option<bool>,
+}
+
+impl Cli {
+    /// Merge the answer file (if any) with flags, flags taking priority, and fall back to the
+    /// user's defaults
+    fn answers(&self) -> Result<Answers> {
+        let mut answers = match &self.answers {
+            Some(path) => Answers::from_file(path, self.non_interactive)?,
+            None => Answers::new(self.non_interactive),
+        };
+
+        let strings = [
+            ("game", &self.game),
+            ("store", &self.store),
+            ("steam_install", &self.steam_install),
+            ("steam_user", &self.steam_user),
+            ("edition", &self.edition),
+            ("proton", &self.proton),
+            ("version", &self.version),
+            ("api_url", &self.api_url),
+            ("repo", &self.repo),
+            ("asset_pattern", &self.asset_pattern),
+            ("token", &self.token),
+        ];
+        for (key, value) in strings {
+            if let Some(value) = value {
+                answers.set(key, value.as_str());
+            }
+        }
+        let paths = [
+            ("install_path", &self.install_path),
+            ("installer", &self.installer),
+            ("game_dir", &self.game_dir),
+            ("prefix", &self.prefix),
+            ("wine", &self.wine),
+            ("artwork", &self.artwork),
+        ];
+        for (key, value) in paths {
+            if let Some(path) = value {
+                answers.set(key, path.display().to_string());
+            }
+        }
+        let bools = [
+            ("desktop_shortcut", self.desktop_shortcut),
+            ("steam_shortcut", self.steam_shortcut),
+            ("controller_config", self.controller_config),
+            ("close_steam", self.close_steam),
+        ];
+        for (key, value) in bools {
+            if let Some(value) = value {
+                answers.set(key, value);
+            }
+        }
+        if self.canary {
+            answers.set("canary", true);
+        }
+        let user = config_handler::user();
+        if answers.get_bool("canary")?.is_none() && user.channel == Some(Channel::Canary) {
+            answers.set("canary", true);
+        }
+
+        Ok(answers)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the mod loader's releases on GitHub
+    ListVersions,
+    /// Reinstall another release over an existing installation, keeping mods and settings
+    Rollback {
+        /// Release tag to install, see list-versions
+        tag: String,
+    },
+    /// Install the newest release over an existing installation, keeping mods, profiles and
+    /// settings
+    Update,
+    /// Switch an installation between the stable and canary update channels
+    Channel {
+        /// stable or canary; asks when not given
+        channel: Option<Channel>,
+        /// Also install the newest release from the new channel
+        #[arg(long)]
+        download: bool,
+    },
+    /// Manage the mod catalogs an installation is subscribed to
+    Catalog {
+        #[command(subcommand)]
+        command: CatalogCommand,
+    },
+    /// Check an installation for common problems
+    Doctor {
+        /// Repair the problems that can be repaired
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Remove an installation and everything the installer added outside it
+    Uninstall {
+        /// Keep mods/ and the loader's settings and profiles
+        #[arg(long)]
+        keep_mods: bool,
+    },
+    /// Inspect or change the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CatalogCommand {
+    /// List the catalogs the installation is subscribed to
+    List,
+    /// Subscribe the installation to a catalog
+    Add {
+        /// URL of the catalog, plain or as an iros://Url/ link
+        url: String,
+        /// Name to show in the mod loader; defaults to the catalog's file name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Unsubscribe the installation from a catalog, by URL or name
+    Remove { catalog: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the settings in effect for --install-path (or just the user defaults) and where
+    /// each one comes from
+    Show,
+    /// Print a setting of the installation, e.g. runner or env.MANGOHUD
+    Get { key: String },
+    /// Change a setting of the installation
+    Set { key: String, value: String },
+    /// Remove a setting from the installation
+    Unset { key: String },
+    /// Set an environment variable for the installation
+    EnvSet { name: String, value: String },
+    /// Remove an environment variable from the installation
+    EnvUnset { name: String },
+    /// Open the installation's config in $VISUAL or $EDITOR, checking it before saving
+    Edit,
+}
+
+static CLI: LazyLock<Cli> = LazyLock::new(Cli::parse);
 
 // Check for Steam Deck
 static IS_DECK: LazyLock<bool> = LazyLock::new(|| {
     std::fs::read_to_string("/etc/os-release")
         .map(|s| ["SteamOS", "Bazzite"].iter().any(|id| s.contains(id)))
         .unwrap_or(false)
-        || env::args().any(|a| a == "-d" || a == "--deck")
+        || CLI.deck
 });
 
 fn main() {
+    LazyLock::force(&CLI);
     if let Err(e) = logging::init("MateriaForge.log") {
         eprintln!("Fatal: {e}");
         std::process::exit(1);
@@ -43,9 +314,35 @@ fn main() {
     log::info!("Starting MateriaForge version {}", VERSION);
     log::info!("Running on Steam Deck: {}", *IS_DECK);
 
-    draw_header();
+    if logging::log_and_return(config_handler::init_user(CLI.config.as_deref())).is_err() {
+        std::process::exit(1);
+    }
+    match logging::log_and_return(CLI.answers()) {
+        Ok(answers) => answers::init(answers),
+        Err(_) => std::process::exit(1),
+    }
 
-    if logging::log_and_return(detect_versions()).is_err() {
+    let result = match &CLI.command {
+        Some(Command::ListVersions) => list_versions(),
+        Some(Command::Rollback { tag }) => rollback(tag),
+        Some(Command::Update) => update(),
+        Some(Command::Channel { channel, download }) => switch_channel(*channel, *download),
+        Some(Command::Catalog { command }) => catalog(command),
+        Some(Command::Doctor { fix }) => doctor(*fix),
+        Some(Command::Uninstall { keep_mods }) => uninstall(*keep_mods),
+        Some(Command::Config {
+            command: ConfigCommand::Show,
+        }) => config_show(),
+        Some(Command::Config {
+            command: ConfigCommand::Edit,
+        }) => config_edit(),
+        Some(Command::Config { command }) => config_change(command),
+        None => {
+            draw_header();
+            detect_versions()
+        }
+    };
+    if logging::log_and_return(result).is_err() {
         std::process::exit(1);
     }
 }
@@ -54,9 +351,10 @@ fn draw_header() {
     let title = format!("Welcome to MateriaForge {VERSION}");
     let mut description = vec![
         "This script will:",
-        "1. Apply patches to FF7's proton prefix to accommodate 7th Heaven",
-        "2. Install 7th Heaven to a folder of your choosing",
-        "3. Add an app launcher shortcut for 7th Heaven",
+        "1. Apply patches to FF7's or FF8's proton prefix to accommodate",
+        "   7th Heaven or Junction VIII",
+        "2. Install the mod loader to a folder of your choosing",
+        "3. Add an app launcher shortcut for the mod loader",
         "4. Optionally add a desktop shortcut and Steam shortcut for easy access",
     ];
     let mut footer = [
@@ -154,247 +452,1308 @@ fn draw_header() {
 }
 
 fn detect_versions() -> Result<()> {
+    // Where each store's installs come from, and whether it sells the GOG release
+    let stores = [
+        (SupportedLaunchers::Steam, "Steam", "steam", Store::Steam),
+        (
+            SupportedLaunchers::HeroicGamesGOG,
+            "Heroic Games",
+            "heroic",
+            Store::Gog,
+        ),
+        (SupportedLaunchers::Lutris, "Lutris", "lutris", Store::Gog),
+    ];
+
     let detector = get_detector();
-    let ff7_installs = detector.get_all_detected_games();
-    let steam_game = ff7_installs.iter().find(|game| {
-        game.title.to_lowercase().contains("final fantasy vii")
-            && game.source == SupportedLaunchers::Steam
-    });
-    let heroic_game = ff7_installs.iter().find(|game| {
-        game.title.to_lowercase().contains("final fantasy vii")
-            && game.source == SupportedLaunchers::HeroicGamesGOG
-    });
+    let installs = detector.get_all_detected_games();
+    let mut detected = Vec::new();
+    for loader in mod_loader::all() {
+        let mut found = Vec::new();
+        for (source, label, id, store) in &stores {
+            if editions::for_loader(loader.id(), *store)?.is_empty() {
+                continue;
+            }
+            if let Some(game) = installs
+                .iter()
+                .find(|game| loader.matches_title(&game.title) && game.source == *source)
+            {
+                found.push((*label, *id, game));
+            }
+        }
+        if !found.is_empty() {
+            detected.push((loader, found));
+        }
+    }
 
-    let game_version = match (steam_game, heroic_game) {
-        (Some(steam), Some(gog)) => {
-            let choices = &["Steam", "Heroic Games"];
+    let custom = answers::get().get_str("store")?.as_deref() == Some("custom");
+    if custom || detected.is_empty() {
+        if !custom {
+            let msg = "Couldn't find FF7 or FF8 in Steam, Heroic or Lutris!";
+            if !answers::get().is_interactive() {
+                bail!("{msg} Pass --store custom to use your own wine prefix.");
+            }
+            println!("{} {msg}", console::style("!").yellow());
+            let choices = &["Yes", "No"];
             let selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
-                .with_prompt("Multiple versions of FF7 detected. Which one do you want to use?")
+                .with_prompt("Is the game installed in a wine prefix you set up yourself?")
                 .default(0)
                 .items(choices)
                 .interact()?;
-
-            match selection {
-                0 => steam,
-                1 => gog,
-                _ => unreachable!(),
+            if selection == 1 {
+                bail!("Couldn't find any supported versions of FF7 or FF8!");
             }
         }
-        (None, Some(gog)) => {
-            log::info!("Heroic Games Launcher install detected!");
-            gog
-        }
-        (Some(steam), None) => {
-            log::info!("Steam install detected!");
-            steam
-        }
-        (None, None) => {
-            bail!("Couldn't find any supported versions of FF7!");
-        }
+
+        let loader = select_loader("Which game do you want to mod?")?;
+        log::info!(
+            "Installing {} for {} in a custom prefix",
+            loader.name(),
+            loader.game()
+        );
+        return run_install(InstallSource::Custom, loader.as_ref());
+    }
+
+    let selection = if detected.len() == 1 && answers::get().get_str("game")?.is_none() {
+        0
+    } else {
+        let choices: Vec<String> = detected
+            .iter()
+            .map(|(l, _)| format!("{} ({})", l.game(), l.name()))
+            .collect();
+        let ids: Vec<String> = detected
+            .iter()
+            .map(|(l, _)| l.game().to_lowercase())
+            .collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        answers::select(
+            "game",
+            "Both FF7 and FF8 were detected. Which one do you want to mod?",
+            &choices,
+            &ids,
+            0,
+        )?
     };
+    let (loader, found) = &detected[selection];
+    log::info!("Installing {} for {}", loader.name(), loader.game());
 
-    run_install(game_version)
+    let game_version = if found.len() == 1 {
+        let (label, _, game) = found[0];
+        log::info!("{label} install detected!");
+        game
+    } else {
+        let choices: Vec<&str> = found.iter().map(|(label, _, _)| *label).collect();
+        let ids: Vec<&str> = found.iter().map(|(_, id, _)| *id).collect();
+        let selection = answers::select(
+            "store",
+            &format!(
+                "Multiple versions of {} detected. Which one do you want to use?",
+                loader.game()
+            ),
+            &choices,
+            &ids,
+            0,
+        )?;
+        found[selection].2
+    };
+
+    run_install(InstallSource::Detected(game_version), loader.as_ref())
 }
 
-fn run_install(found_game: &lib_game_detector::data::Game) -> Result<()> {
-    let mut config = HashMap::new();
+/// Pick a mod loader by the game it mods, without looking for installed games
+fn select_loader(prompt: &str) -> Result<Box<dyn ModLoader>> {
+    let mut loaders = mod_loader::all();
+    let choices: Vec<String> = loaders
+        .iter()
+        .map(|l| format!("{} ({})", l.game(), l.name()))
+        .collect();
+    let ids: Vec<String> = loaders.iter().map(|l| l.game().to_lowercase()).collect();
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    let selection = answers::select("game", prompt, &choices, &ids, 0)?;
+    Ok(loaders.remove(selection))
+}
+
+/// Where the game to mod was found
+enum InstallSource<'a> {
+    Detected(&'a lib_game_detector::data::Game),
+    /// A prefix the user supplies the paths for
+    Custom,
+}
+
+fn run_install(source: InstallSource, loader: &dyn ModLoader) -> Result<()> {
+    manifest::undo_unsaved(|| install_game(source, loader))
+}
+
+fn install_game(source: InstallSource, loader: &dyn ModLoader) -> Result<()> {
+    // app_id is filled in once the game has been found
+    let mut config = Config::new(InstallType::Steam, loader.id(), 0);
     let game: Box<dyn PrefixedGame>;
     let steam_dir: Option<steamlocate::SteamDir> = gamelib_helper::steam_lib::get_library().ok();
 
+    let found_game = match source {
+        InstallSource::Detected(found_game) => found_game,
+        InstallSource::Custom => {
+            config.install_type = InstallType::Custom;
+            let custom_game = get_custom_game(loader, &mut config)?;
+            return finish_install(config, Box::new(custom_game), steam_dir, loader, true);
+        }
+    };
     match found_game.source {
         SupportedLaunchers::Steam => {
             if steam_dir.is_none() {
                 bail!("Selected Steam game, but no Steam library could be found?");
             }
-            config.insert("type", "steam".to_string());
+            config.install_type = InstallType::Steam;
 
             let steam_dir = gamelib_helper::steam_lib::get_library()?;
-            config.insert("steam_dir", steam_dir.path().display().to_string());
-
-            let (original, remaster) = with_spinner("Finding FF7...", "Done!", || {
-                let original =
-                    gamelib_helper::steam_game::get_game(FF7_APPID, steam_dir.clone()).ok();
-                let remaster =
-                    gamelib_helper::steam_game::get_game(FF7_2026_APPID, steam_dir.clone()).ok();
-                if original.is_none() && remaster.is_none() {
-                    bail!("Couldn't find any supported Steam version of FF7");
-                }
-                Ok((original, remaster))
-            })?;
-
-            let mut steam_game = match (original, remaster) {
-                (Some(og), Some(rm)) => {
-                    let choices = &[&og.name, &format!("{} (2026)", rm.name)];
-                    let selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
-                        .with_prompt("Multiple Steam installations of FF7 were detected. Which one do you want to patch?")
-                        .default(0)
-                        .items(choices)
-                        .interact()
-                        .context("Selection failed")?;
-
-                    match selection {
-                        0 => og,
-                        1 => rm,
-                        _ => unreachable!(),
+            config.steam_dir = Some(steam_dir.path().to_path_buf());
+
+            let mut found =
+                with_spinner(&format!("Finding {}...", loader.game()), "Done!", || {
+                    let found: Vec<_> = editions::for_loader(loader.id(), Store::Steam)?
+                        .into_iter()
+                        .filter_map(|edition| {
+                            gamelib_helper::steam_game::get_game(edition.app_id, steam_dir.clone())
+                                .ok()
+                                .map(|game| (game, edition))
+                        })
+                        .collect();
+                    if found.is_empty() {
+                        bail!(
+                            "Couldn't find any supported Steam version of {}",
+                            loader.game()
+                        );
                     }
-                }
-                (Some(og), None) => og,
-                (None, Some(rm)) => rm,
-                (None, None) => unreachable!(),
+                    Ok(found)
+                })?;
+
+            let mut steam_game = if found.len() == 1 {
+                found.remove(0).0
+            } else {
+                let choices: Vec<String> = found
+                    .iter()
+                    .map(|(g, edition)| format!("{} {}", g.name, edition.suffix))
+                    .collect();
+                let ids: Vec<&str> = found.iter().map(|(_, e)| e.edition.as_str()).collect();
+                let selection = answers::select(
+                    "edition",
+                    &format!(
+                        "Multiple Steam installations of {} were detected. Which one do you want to patch?",
+                        loader.game()
+                    ),
+                    &choices,
+                    &ids,
+                    0,
+                )?;
+                found.remove(selection).0
             };
 
-            steam_game.runner = Some(gamelib_helper::steam_game::select_runner(&steam_game)?);
+            let previous =
+                gamelib_helper::steam_game::compat_tool(&steam_game.client_path, steam_game.app_id);
+            let runner = gamelib_helper::steam_game::select_runner(&steam_game)?;
+            manifest::record(Change::CompatTool {
+                steam_dir: steam_game.client_path.clone(),
+                app_id: steam_game.app_id,
+                previous,
+            });
+            // Keep a choice that differs from the user's preferred Proton, which would otherwise
+            // take over at launch
+            if config_handler::user()
+                .runner
+                .as_ref()
+                .is_some_and(|preferred| *preferred != runner.name)
+            {
+                config.runner = Some(runner.name.clone());
+            }
+            steam_game.runner = Some(runner);
             game = Box::new(steam_game);
         }
         SupportedLaunchers::HeroicGamesGOG => {
-            config.insert("type", "gog".to_string());
+            config.install_type = InstallType::Gog;
             game = Box::new(
-                gog_game::get_game(FF7_GOG_APPID, &found_game)
-                    .context("Failed to get GOG game details")?,
+                gog_game::get_game(
+                    editions::for_loader(loader.id(), Store::Gog)?
+                        .first()
+                        .context("Loader doesn't support GOG")?
+                        .app_id,
+                    found_game,
+                )
+                .context("Failed to get GOG game details")?,
+            );
+        }
+        SupportedLaunchers::Lutris => {
+            config.install_type = InstallType::Lutris;
+            game = Box::new(
+                lutris_game::get_game(
+                    editions::for_loader(loader.id(), Store::Gog)?
+                        .first()
+                        .context("Loader doesn't support GOG")?
+                        .app_id,
+                    found_game,
+                )
+                .context("Failed to get Lutris game details")?,
             );
         }
         _ => bail!("Unsupported game selected"),
     }
-    config.insert("app_id", game.app_id().to_string());
+    finish_install(config, game, steam_dir, loader, false)
+}
 
-    let choices = &["Yes", "No"];
-    let selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Do you want to continue installing 7th Heaven?")
-        .default(0) // Default to "Yes"
-        .items(choices)
-        .interact()
-        .unwrap();
+/// Ask for the game directory, prefix and runner of a game installed outside any supported
+/// store, recording them in `config`
+fn get_custom_game(loader: &dyn ModLoader, config: &mut Config) -> Result<custom_game::CustomGame> {
+    let game_dir = answers::path(
+        "game_dir",
+        &format!("Path to the {} game directory", loader.game()),
+    )?;
+    let mut candidates = editions::for_loader(loader.id(), Store::Steam)?;
+    candidates.extend(editions::for_loader(loader.id(), Store::Gog)?);
+    let found = custom_game::matching_editions(&candidates, &game_dir);
+    let edition = match found.len() {
+        0 => bail!(
+            "Couldn't find {} in '{}' (looked for {})",
+            loader.game(),
+            game_dir.display(),
+            candidates
+                .iter()
+                .map(|e| e.exe.as_str())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        1 => found[0],
+        _ => {
+            let choices: Vec<String> = found
+                .iter()
+                .map(|e| format!("{} {}", loader.game(), e.suffix))
+                .collect();
+            let ids: Vec<&str> = found.iter().map(|e| e.edition.as_str()).collect();
+            let selection = answers::select(
+                "edition",
+                &format!("Which release of {} is installed there?", loader.game()),
+                &choices,
+                &ids,
+                0,
+            )?;
+            found[selection]
+        }
+    };
 
-    if selection == 1 {
-        // No
-        println!("Understood. Exiting.");
-        std::process::exit(0);
+    let prefix = answers::path("prefix", "Path to the wine prefix")?;
+    let wine = answers::path(
+        "wine",
+        "Path to the wine binary (or a GE-Proton `proton` script)",
+    )?;
+    let game = custom_game::get_game(edition, &game_dir, &prefix, &wine)?;
+
+    config.game_dir = Some(game_dir);
+    config.prefix = Some(prefix);
+    config.wine = Some(wine);
+    Ok(game)
+}
+
+fn finish_install(
+    mut config: Config,
+    game: Box<dyn PrefixedGame>,
+    steam_dir: Option<steamlocate::SteamDir>,
+    loader: &dyn ModLoader,
+    custom: bool,
+) -> Result<()> {
+    let mut edition = editions::by_app_id(game.app_id())?.clone();
+    // Only Steam's Proton maps the S: drive, anything else sees the game through Z:
+    if custom {
+        edition.drive = Drive::Z;
+    }
+    let edition = &edition;
+    config.app_id = game.app_id();
+
+    if answers::get().is_interactive() {
+        let choices = &["Yes", "No"];
+        let selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Do you want to continue installing {}?",
+                loader.name()
+            ))
+            .default(0) // Default to "Yes"
+            .items(choices)
+            .interact()
+            .unwrap();
+
+        if selection == 1 {
+            // No
+            println!("Understood. Exiting.");
+            return Ok(());
+        }
+    }
+
+    let channel = answered_channel()?;
+    let tag = answers::get().get_str("version")?;
+    let (exe_path, version) = get_installer(loader, channel == Channel::Canary, tag.as_deref())?;
+    log::info!("Installing {} {version}", loader.name());
+    config.version = Some(version);
+    config.channel = Some(channel);
+    config
+        .env
+        .insert("WINEDEBUG".to_string(), DEFAULT_WINEDEBUG.to_string());
+
+    config
+        .save(&config_handler::exe_dir()?)
+        .context("Failed to write config")?;
+
+    let install_path = get_install_path(loader)?;
+    let env = config.clone().with_defaults(config_handler::user()).env;
+
+    let finish = || -> Result<()> {
+        with_spinner(&format!("Installing {}...", loader.name()), "Done!", || {
+            mod_loader::install(
+                loader,
+                edition,
+                game.as_ref(),
+                exe_path,
+                &install_path,
+                launcher_bin(),
+                &env,
+            )
+        })?;
+
+        with_spinner("Patching installation...", "Done!", || {
+            mod_loader::patch(
+                loader,
+                edition,
+                game.as_ref(),
+                &install_path,
+                channel.update_channel(),
+            )
+        })?;
+
+        let mut steam_users = None;
+        let steam_shortcut = create_shortcuts(
+            &install_path,
+            steam_dir.clone(),
+            &mut steam_users,
+            edition,
+            loader,
+        )
+        .context("Failed to create shortcuts")?;
+
+        add_controller_config(
+            edition,
+            &steam_dir,
+            &mut steam_users,
+            steam_shortcut.as_ref(),
+        )
+        .context("Failed to set controller config")
+    };
+    // Save the manifest even if a step failed, so what was done can still be undone
+    let result = finish();
+    manifest::finish()
+        .save(&install_path)
+        .context("Failed to write install manifest")?;
+    result?;
+    if let Err(e) = installs::register(&install_path) {
+        log::warn!("Couldn't register installation: {e:#}");
+    }
+
+    println!(
+        "{} {} successfully installed to '{}'",
+        console::style("✔").green(),
+        loader.name(),
+        console::style(&install_path.display())
+            .bold()
+            .underlined()
+            .white()
+    );
+
+    Ok(())
+}
+
+/// Find the loader's installer and its version: the one passed with `--installer`, a fresh
+/// download, or the newest cached download when GitHub can't be reached.
+///
+/// `tag` pins a release; otherwise the latest (pre)release is used.
+fn get_installer(
+    loader: &dyn ModLoader,
+    prerelease: bool,
+    tag: Option<&str>,
+) -> Result<(PathBuf, String)> {
+    if let Some(path) = answers::get().get_str("installer")? {
+        let path = PathBuf::from(path);
+        if !path.is_file() {
+            bail!("Installer '{}' doesn't exist", path.display());
+        }
+        let version = path
+            .file_name()
+            .and_then(|name| downloader::version_from_file_name(&name.to_string_lossy()))
+            .unwrap_or_else(|| "local".to_string());
+        println!(
+            "{} Using local installer '{}'",
+            console::style("!").yellow(),
+            console::style(path.display()).bold().underlined().white()
+        );
+        return Ok((path, version));
     }
 
-    let use_canary = env::args().any(|a| a == "-c" || a == "--canary");
-    let update_channel = match use_canary {
-        true => "Canary",
-        false => "Stable",
+    let cache_dir = installer_cache_dir(loader)?;
+    let source = ReleaseSource::for_loader(loader)?;
+    let channel = match prerelease {
+        true => Channel::Canary,
+        false => Channel::Stable,
+    };
+
+    let release = match source.release(prerelease, tag) {
+        Ok(release) => release,
+        Err(e) => {
+            log::warn!("Couldn't reach {}: {e:#}", source.api_url);
+            let (path, version) = downloader::latest_cached(&cache_dir, tag, channel, |name| {
+                source.is_installer_asset(loader, name)
+            })
+            .with_context(|| {
+                format!(
+                    "Couldn't fetch {} {} and no matching installer is cached in '{}'. Pass --installer to use a local copy.",
+                    loader.name(),
+                    tag.unwrap_or("latest"),
+                    cache_dir.display()
+                )
+            })?;
+            println!(
+                "{} {} is unreachable, using cached {} {version}",
+                console::style("!").yellow(),
+                source.api_url,
+                loader.name()
+            );
+            return Ok((path, version));
+        }
+    };
+
+    let version = release["tag_name"]
+        .as_str()
+        .context("Release has no tag name")?
+        .to_string();
+    let release_dir = cache_dir.join(&version);
+    let exe_path = download_asset(&source, loader, &release, &release_dir)
+        .with_context(|| format!("Failed to download {}!", loader.name()))?;
+    let release_channel = match release["prerelease"].as_bool().unwrap_or(false) {
+        true => Channel::Canary,
+        false => Channel::Stable,
     };
+    if let Err(e) = downloader::set_cached_channel(&release_dir, release_channel) {
+        log::warn!("{e:#}");
+    }
+    Ok((exe_path, version))
+}
 
-    let cache_dir = home::home_dir()
-        .context("Couldn't find $HOME?")?
-        .join(".cache");
+/// The launcher binary shipped alongside MateriaForge
+fn launcher_bin() -> &'static Path {
+    if cfg!(debug_assertions) {
+        Path::new("target/debug/launcher")
+    } else {
+        Path::new("launcher")
+    }
+}
 
-    let exe_path = download_asset("tsunamods-codes/7th-Heaven", cache_dir, use_canary)
-        .expect("Failed to download 7th Heaven!");
+fn installer_cache_dir(loader: &dyn ModLoader) -> Result<PathBuf> {
+    Ok(xdg::BaseDirectories::with_prefix("MateriaForge")
+        .get_cache_home()
+        .context("Couldn't get cache_home")?
+        .join(loader.id()))
+}
+
+fn list_versions() -> Result<()> {
+    let loader = select_loader("Which mod loader's releases do you want to list?")?;
+    let releases = ReleaseSource::for_loader(loader.as_ref())?
+        .releases()
+        .with_context(|| format!("Couldn't list {} releases", loader.name()))?;
+    let cache_dir = installer_cache_dir(loader.as_ref())?;
+
+    println!("{} releases:", loader.name());
+    for release in &releases {
+        let tag = release["tag_name"].as_str().unwrap_or("?");
+        let date = release["published_at"]
+            .as_str()
+            .and_then(|date| date.get(..10))
+            .unwrap_or("");
+        let mut notes = Vec::new();
+        if release["prerelease"].as_bool().unwrap_or(false) {
+            notes.push("canary");
+        }
+        if cache_dir.join(tag).is_dir() {
+            notes.push("cached");
+        }
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!("({})", notes.join(", "))
+        };
+        println!("  {tag:<20} {date}  {}", console::style(notes).dim());
+    }
+    Ok(())
+}
 
-    let mut env_vars = std::collections::HashMap::new();
-    env_vars.insert("WINEDEBUG", DEFAULT_WINEDEBUG.to_string());
+fn rollback(tag: &str) -> Result<()> {
+    let install_path = select_install("Which installation do you want to roll back?")?;
+    let (config, loader) = load_install(&install_path)?;
+    if config.version.as_deref() == Some(tag) {
+        println!(
+            "{} {} {tag} is already installed",
+            console::style("!").yellow(),
+            loader.name()
+        );
+        return Ok(());
+    }
 
-    config_handler::write(config, env_vars).context("Failed to write config")?;
+    let config = config.with_defaults(config_handler::user());
+    let game = gamelib_helper::from_config(&config, loader.as_ref())?;
+    let (exe_path, version) = get_installer(loader.as_ref(), false, Some(tag))?;
+    with_spinner(
+        &format!("Installing {} {version}...", loader.name()),
+        "Done!",
+        || {
+            with_manifest(&install_path, || {
+                mod_loader::reinstall(
+                    loader.as_ref(),
+                    game.as_ref(),
+                    exe_path,
+                    &install_path,
+                    &config.env,
+                )
+            })
+        },
+    )?;
+    let mut file = ConfigFile::open(&install_path)?;
+    file.set("version", &version)?;
+    with_manifest(&install_path, || file.save())?;
 
-    let install_path = get_install_path()?;
-    with_spinner("Installing 7th Heaven...", "Done!", || {
-        install_7th(game.as_ref(), exe_path, &install_path, "7thHeaven.log")
-    })?;
+    println!(
+        "{} Rolled back '{}' to {} {version}",
+        console::style("✔").green(),
+        console::style(install_path.display())
+            .bold()
+            .underlined()
+            .white(),
+        loader.name()
+    );
+    Ok(())
+}
 
-    with_spinner("Patching installation...", "Done!", || {
-        patch_install(&install_path, game.as_ref(), update_channel)
-    })?;
+/// Pick one of the installations MateriaForge can find, unless `--install-path` names one
+fn select_install(prompt: &str) -> Result<PathBuf> {
+    if let Some(path) = answers::get().get_str("install_path")? {
+        return Ok(PathBuf::from(path));
+    }
+    let mut found = installs::find()?;
+    if found.len() == 1 {
+        return Ok(found.remove(0));
+    }
+    if found.is_empty() {
+        if !answers::get().is_interactive() {
+            return Err(answers::missing("install_path"));
+        }
+        return answers::path("install_path", prompt);
+    }
+    let choices: Vec<String> = found
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    let ids: Vec<&str> = choices.iter().map(String::as_str).collect();
+    let selection = answers::select("install_path", prompt, &choices, &ids, 0)?;
+    Ok(found.remove(selection))
+}
 
-    let (steam_shortcut, _) = create_shortcuts(&install_path, steam_dir.clone(), game.app_id())
-        .context("Failed to create shortcuts")?;
+/// The update channel chosen with --canary or the answer file
+fn answered_channel() -> Result<Channel> {
+    Ok(match answers::get().get_bool("canary")?.unwrap_or(false) {
+        true => Channel::Canary,
+        false => Channel::Stable,
+    })
+}
 
-    add_controller_config(game.as_ref(), &steam_dir, steam_shortcut)
-        .context("Failed to set controller config")?;
+/// The config and loader of the installation at `install_path`
+fn load_install(install_path: &Path) -> Result<(Config, Box<dyn ModLoader>)> {
+    let config = config_handler::load(install_path)?;
+    let loader = mod_loader::from_id(&config.loader)
+        .with_context(|| format!("Unknown loader '{}' in config", config.loader))?;
+    if !install_path.join(loader.exe_name()).is_file() {
+        bail!(
+            "'{}' isn't a {} installation",
+            install_path.display(),
+            loader.name()
+        );
+    }
+    Ok((config, loader))
+}
+
+fn update() -> Result<()> {
+    let install_path = select_install("Which installation do you want to update?")?;
+    let (config, loader) = load_install(&install_path)?;
+    // Stay on the installation's channel unless --canary asks for another one
+    let channel = match (CLI.canary, config.channel) {
+        (false, Some(channel)) => channel,
+        _ => answered_channel()?,
+    };
+    update_install(&install_path, config, loader.as_ref(), channel)
+}
+
+/// Install the newest release from `channel` (or --version) over the installation
+fn update_install(
+    install_path: &Path,
+    config: Config,
+    loader: &dyn ModLoader,
+    channel: Channel,
+) -> Result<()> {
+    let use_canary = channel == Channel::Canary;
+    let answers = answers::get();
+    let mut tag = answers.get_str("version")?;
+    if tag.is_none() && answers.get_str("installer")?.is_none() {
+        // Pin the release now so a new one appearing mid-update can't be mixed in
+        match ReleaseSource::for_loader(loader)?.release(use_canary, None) {
+            Ok(release) => tag = release["tag_name"].as_str().map(String::from),
+            Err(e) => log::warn!("Couldn't check for a newer release: {e:#}"),
+        }
+    }
+    if tag.is_some() && tag == config.version {
+        println!(
+            "{} {} {} is already up to date",
+            console::style("✔").green(),
+            loader.name(),
+            config.version.as_deref().unwrap_or_default()
+        );
+        return Ok(());
+    }
+
+    let config = config.with_defaults(config_handler::user());
+    let game = gamelib_helper::from_config(&config, loader)?;
+    let (exe_path, version) = get_installer(loader, use_canary, tag.as_deref())?;
+
+    let mut manifest = Manifest::load(install_path)?;
+    manifest::begin();
+    let result = with_spinner(
+        &format!("Updating {} to {version}...", loader.name()),
+        "Done!",
+        || {
+            mod_loader::update(
+                loader,
+                &config,
+                game.as_ref(),
+                exe_path,
+                install_path,
+                launcher_bin(),
+                channel.update_channel(),
+            )
+        },
+    )
+    .and_then(|()| {
+        let mut file = ConfigFile::open(install_path)?;
+        file.set("version", &version)?;
+        file.set("channel", channel.as_str())?;
+        file.save()
+    });
+    manifest.merge(manifest::finish());
+    manifest
+        .save(install_path)
+        .context("Failed to write install manifest")?;
+    result?;
+
+    let previous = config.version;
+    if let Err(e) = installs::register(install_path) {
+        log::warn!("Couldn't register installation: {e:#}");
+    }
 
     println!(
-        "{} 7th Heaven successfully installed to '{}'",
+        "{} Updated '{}' from {} to {} {version}",
         console::style("✔").green(),
-        console::style(&install_path.display())
+        console::style(install_path.display())
+            .bold()
+            .underlined()
+            .white(),
+        previous.as_deref().unwrap_or("an unknown version"),
+        loader.name()
+    );
+    Ok(())
+}
+
+fn switch_channel(channel: Option<Channel>, download: bool) -> Result<()> {
+    let install_path = select_install("Which installation do you want to switch?")?;
+    let (config, loader) = load_install(&install_path)?;
+    let current = config.channel.unwrap_or_default();
+    let channel = match channel {
+        Some(channel) => channel,
+        None => {
+            let channels = [Channel::Stable, Channel::Canary];
+            let choices = ["Stable", "Canary (pre-releases)"];
+            let selection = answers::select(
+                "channel",
+                &format!("Which update channel should {} follow?", loader.name()),
+                &choices,
+                &["stable", "canary"],
+                // Suggest the channel the installation isn't on
+                usize::from(current == Channel::Stable),
+            )?;
+            channels[selection]
+        }
+    };
+
+    with_manifest(&install_path, || {
+        mod_loader::set_update_channel(loader.as_ref(), &install_path, channel.update_channel())
+    })
+    .context("Failed to update settings.xml")?;
+
+    let mut file = ConfigFile::open(&install_path)?;
+    file.set("channel", channel.as_str())?;
+    with_manifest(&install_path, || file.save())?;
+    println!(
+        "{} '{}' now follows the {channel} channel",
+        console::style("✔").green(),
+        console::style(install_path.display())
             .bold()
             .underlined()
             .white()
     );
 
+    // An answer file can ask for the download; otherwise it's only offered when the channel
+    // changed, and an unattended run doesn't download without being asked to
+    let download = download
+        || match answers::get().get_bool("download")? {
+            Some(answer) => answer,
+            None => {
+                channel != current
+                    && answers::get().is_interactive()
+                    && answers::confirm(
+                        "download",
+                        &format!(
+                            "Install the newest {channel} release of {} now?",
+                            loader.name()
+                        ),
+                    )?
+            }
+        };
+    if download {
+        let config = config_handler::load(&install_path)?;
+        update_install(&install_path, config, loader.as_ref(), channel)?;
+    }
     Ok(())
 }
 
-fn download_asset(repo: &str, destination: PathBuf, prerelease: bool) -> Result<PathBuf> {
-    let client = reqwest::blocking::Client::new();
-
-    let response: serde_json::Value = if prerelease {
-        let releases_url = format!("https://api.github.com/repos/{repo}/releases");
-        let releases: Vec<serde_json::Value> = client
-            .get(&releases_url)
-            .header("User-Agent", "rust-client")
-            .send()?
-            .json()?;
-        releases
-            .into_iter()
-            .next()
-            .context("No releases found in GitHub repo")?
+/// Run `f`, adding what it writes to the installation's manifest even if it fails
+fn with_manifest<T>(install_path: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let mut manifest = Manifest::load(install_path)?;
+    manifest::begin();
+    let result = f();
+    manifest.merge(manifest::finish());
+    manifest
+        .save(install_path)
+        .context("Failed to write install manifest")?;
+    result
+}
+
+fn catalog(command: &CatalogCommand) -> Result<()> {
+    let install_path = select_install("Which installation's catalogs do you want to manage?")?;
+    let (_, loader) = load_install(&install_path)?;
+    let mut settings = Settings::load(&mod_loader::settings_path(loader.as_ref(), &install_path))?;
+
+    match command {
+        CatalogCommand::List => {
+            let catalogs = settings.catalogs()?;
+            if catalogs.is_empty() {
+                println!("Not subscribed to any catalogs");
+            }
+            let width = catalogs.iter().map(|c| c.name().len()).max().unwrap_or(0);
+            for catalog in catalogs {
+                println!(
+                    "{:width$}  {}",
+                    console::style(catalog.name()).bold(),
+                    catalog.url
+                );
+            }
+        }
+        CatalogCommand::Add { url, name } => {
+            let catalog = Catalog {
+                url: settings_xml::plain_url(url),
+                name: name.clone(),
+            };
+            if !catalog.url.contains("://") {
+                bail!("'{url}' isn't a URL");
+            }
+            if !settings.add_catalog(&catalog)? {
+                println!(
+                    "{} Already subscribed to {}",
+                    console::style("!").yellow(),
+                    catalog.url
+                );
+                return Ok(());
+            }
+            with_manifest(&install_path, || settings.save())?;
+            println!(
+                "{} Subscribed to {} ({})",
+                console::style("✔").green(),
+                catalog.name(),
+                catalog.url
+            );
+        }
+        CatalogCommand::Remove { catalog } => {
+            let removed = settings.remove_catalog(catalog)?;
+            if removed.is_empty() {
+                bail!("Not subscribed to a catalog named or at '{catalog}'");
+            }
+            with_manifest(&install_path, || settings.save())?;
+            for catalog in removed {
+                println!(
+                    "{} Unsubscribed from {} ({})",
+                    console::style("✔").green(),
+                    catalog.name(),
+                    catalog.url
+                );
+                if config_handler::user()
+                    .catalogs
+                    .iter()
+                    .any(|default| default.url == catalog.url)
+                {
+                    println!(
+                        "{} It's in your defaults, so `update` will subscribe again; remove it from {} too",
+                        console::style("!").yellow(),
+                        config_handler::user_path().display()
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn doctor(fix: bool) -> Result<()> {
+    let install_path = select_install("Which installation do you want to check?")?;
+    let mut install = doctor::Install::load(&install_path)?;
+    let mut manifest = Manifest::load(&install_path)?;
+    manifest::begin();
+
+    let mut failed = 0;
+    for check in doctor::CHECKS {
+        let (problem, hint) = match check.run(&install) {
+            Status::Pass => {
+                println!("{} {}", console::style("✔").green(), check.name);
+                continue;
+            }
+            Status::Skipped(reason) => {
+                println!(
+                    "{} {}: skipped, {reason}",
+                    console::style("-").dim(),
+                    check.name
+                );
+                continue;
+            }
+            Status::Fail { problem, hint } => (problem, hint),
+        };
+
+        if fix && check.can_fix() {
+            let fixed = check
+                .fix(&mut install)
+                .and_then(|()| match check.run(&install) {
+                    Status::Fail { problem, .. } => bail!(problem),
+                    _ => Ok(()),
+                });
+            match fixed {
+                Ok(()) => {
+                    println!(
+                        "{} {}: fixed ({problem})",
+                        console::style("✔").green(),
+                        check.name
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    println!("{} {}: {problem}", console::style("✘").red(), check.name);
+                    println!("    Couldn't fix it: {e:#}");
+                }
+            }
+        } else {
+            println!("{} {}: {problem}", console::style("✘").red(), check.name);
+            println!("    {}", console::style(hint).dim());
+        }
+        failed += 1;
+    }
+
+    manifest.merge(manifest::finish());
+    manifest
+        .save(&install_path)
+        .context("Failed to write install manifest")?;
+    if failed > 0 {
+        bail!("{failed} check(s) failed");
+    }
+    Ok(())
+}
+
+/// Every file the manifests of installs other than `install_path` list
+fn other_installs_files(install_path: &Path) -> Vec<PathBuf> {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let this = canonical(install_path);
+    let others = installs::find().unwrap_or_else(|e| {
+        log::warn!("Couldn't list other installations: {e:#}");
+        Vec::new()
+    });
+    others
+        .into_iter()
+        .filter(|path| canonical(path) != this)
+        .filter_map(|path| Manifest::load(&path).ok())
+        .flat_map(|manifest| manifest.files.into_iter().map(|file| file.path))
+        .collect()
+}
+
+fn uninstall(keep_mods: bool) -> Result<()> {
+    let install_path = select_install("Which installation do you want to remove?")?;
+    let (_, loader) = load_install(&install_path)?;
+    let manifest = Manifest::load(&install_path)?;
+    let kept = ["mods", loader.workshop_dir()];
+    let shared = other_installs_files(&install_path);
+    // Files inside the install go with it, and files that were already there or that another
+    // install also wrote (e.g. Steam's controller template) stay
+    let created: Vec<&Path> = manifest
+        .files
+        .iter()
+        .filter(|file| {
+            !file.existed
+                && file.kind != FileKind::SteamConfig
+                && !file.path.starts_with(&install_path)
+                && !shared.contains(&file.path)
+        })
+        .map(|file| file.path.as_path())
+        .collect();
+
+    // Proton mappings of the shortcut go with it, so restoring them would leave orphans behind
+    let mut shortcut_ids = Vec::new();
+    for change in &manifest.changes {
+        if let Change::SteamShortcut { steam_dir, exe } = change {
+            match gamelib_helper::steam_lib::nonsteam_app_ids(steam_dir, exe) {
+                Ok(app_ids) => shortcut_ids.extend(app_ids),
+                Err(e) => log::warn!("Couldn't read the app ID of {exe:?}: {e:#}"),
+            }
+        }
+    }
+    let changes: Vec<&Change> = manifest
+        .changes
+        .iter()
+        .rev()
+        .filter(|change| match change {
+            Change::CompatTool { app_id, .. } => !shortcut_ids.contains(app_id),
+            _ => true,
+        })
+        .collect();
+
+    println!("{} This will:", console::style("!").yellow());
+    for change in &changes {
+        println!("  - {}", change.describe());
+    }
+    for path in &created {
+        println!("  - Remove {}", path.display());
+    }
+    match keep_mods {
+        true => println!(
+            "  - Remove {} except {}",
+            install_path.display(),
+            kept.join(" and ")
+        ),
+        false => println!("  - Remove {}", install_path.display()),
+    }
+    if manifest.changes.is_empty() && manifest.files.is_empty() {
+        println!(
+            "{} No record of shortcuts or Steam settings was found for this installation; remove them by hand",
+            console::style("!").yellow()
+        );
+    }
+    if answers::get().is_interactive()
+        && !dialoguer::Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Uninstall {}?", loader.name()))
+            .default(false)
+            .interact()?
+    {
+        println!("Understood. Exiting.");
+        return Ok(());
+    }
+
+    // Files come off first, so a backup restored in their place stays
+    for path in created {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                log::warn!("Couldn't remove {}: {e}", path.display());
+                println!(
+                    "{} Couldn't remove {}: {e}",
+                    console::style("!").yellow(),
+                    path.display()
+                );
+            }
+            _ => {}
+        }
+    }
+    // Undo in reverse so later changes come off before the ones they built on
+    for change in changes {
+        if let Err(e) = change.undo() {
+            log::warn!("{}: {e:#}", change.describe());
+            println!("{} {e:#}", console::style("!").yellow());
+        }
+    }
+
+    if keep_mods {
+        for entry in std::fs::read_dir(&install_path)? {
+            let entry = entry?;
+            if kept.iter().any(|dir| entry.file_name() == *dir) {
+                continue;
+            }
+            let path = entry.path();
+            let removed = match entry.file_type()?.is_dir() {
+                true => std::fs::remove_dir_all(&path),
+                false => std::fs::remove_file(&path),
+            };
+            removed.with_context(|| format!("Couldn't remove '{}'", path.display()))?;
+        }
     } else {
-        let release_url = format!("https://api.github.com/repos/{repo}/releases/latest");
-        client
-            .get(&release_url)
-            .header("User-Agent", "rust-client")
-            .send()?
-            .json()?
+        std::fs::remove_dir_all(&install_path)
+            .with_context(|| format!("Couldn't remove '{}'", install_path.display()))?;
+    }
+    if let Err(e) = installs::unregister(&install_path) {
+        log::warn!("Couldn't unregister installation: {e:#}");
+    }
+
+    println!(
+        "{} Uninstalled {} from '{}'",
+        console::style("✔").green(),
+        loader.name(),
+        console::style(install_path.display())
+            .bold()
+            .underlined()
+            .white()
+    );
+    Ok(())
+}
+
+fn config_show() -> Result<()> {
+    let install_path = answers::get().get_str("install_path")?.map(PathBuf::from);
+    let settings = config_handler::effective(install_path.as_deref())?;
+    if settings.is_empty() {
+        println!(
+            "{} Nothing is configured; create {} to set defaults",
+            console::style("!").yellow(),
+            config_handler::user_config_path()?.display()
+        );
+        return Ok(());
+    }
+
+    let width = settings.keys().map(|key| key.len()).max().unwrap_or(0);
+    for (key, (value, source)) in settings {
+        println!(
+            "{key:<width$} = {value}  {}",
+            console::style(format!("# {}", source.display())).dim()
+        );
+    }
+    Ok(())
+}
+
+/// Apply a get, set or unset to the config of the installation at `--install-path`
+fn config_change(command: &ConfigCommand) -> Result<()> {
+    let install_path = select_install("Which installation do you want to configure?")?;
+    let mut file = ConfigFile::open(&install_path)?;
+
+    let done = match command {
+        ConfigCommand::Get { key } => {
+            let item = match key.split_once('.') {
+                Some(("env", name)) => file
+                    .get("env")
+                    .and_then(|env| env.as_table_like())
+                    .and_then(|env| env.get(name)),
+                _ => file.get(key),
+            };
+            let item = item.with_context(|| format!("`{key}` isn't set"))?;
+            match item.as_value() {
+                Some(toml_edit::Value::String(value)) => println!("{}", value.value()),
+                // Without the comments around it
+                Some(value) => println!("{}", value.clone().decorated("", "")),
+                None => println!("{}", item.to_string().trim()),
+            }
+            return Ok(());
+        }
+        ConfigCommand::Set { key, value } => {
+            file.set(key, value)?;
+            format!("Set {key}")
+        }
+        ConfigCommand::Unset { key } => {
+            if !file.unset(key)? {
+                bail!("`{key}` isn't set");
+            }
+            format!("Removed {key}")
+        }
+        ConfigCommand::EnvSet { name, value } => {
+            file.env_set(name, value)?;
+            format!("Set env.{name}")
+        }
+        ConfigCommand::EnvUnset { name } => {
+            if !file.env_unset(name) {
+                bail!("`env.{name}` isn't set");
+            }
+            format!("Removed env.{name}")
+        }
+        ConfigCommand::Show | ConfigCommand::Edit => unreachable!(),
     };
 
-    let assets = response["assets"]
-        .as_array()
-        .context("No assets found in GitHub link")?;
+    check_runner(&file.config()?)?;
+    with_manifest(&install_path, || file.save())?;
+    println!(
+        "{} {done} in '{}'",
+        console::style("✔").green(),
+        console::style(file.path.display())
+            .bold()
+            .underlined()
+            .white()
+    );
+    Ok(())
+}
 
-    let exe_asset = assets
-        .iter()
-        .find(|a| a["name"].as_str().unwrap_or("").ends_with(".exe"))
-        .context("No .exe asset found")?;
+/// Edit the config of the installation at `--install-path` in the user's editor, only saving it
+/// once it's valid
+fn config_edit() -> Result<()> {
+    if !answers::get().is_interactive() {
+        bail!("`config edit` needs an editor; use `config set` when running non-interactively");
+    }
+    let install_path = select_install("Which installation do you want to configure?")?;
+    let file = ConfigFile::open(&install_path)?;
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut editor_args = editor.split_whitespace();
+    let program = editor_args.next().context("$EDITOR is empty")?;
+
+    // Work on a copy so an invalid file never replaces the working one
+    let draft = env::temp_dir().join(format!("MateriaForge-{}.toml", std::process::id()));
+    std::fs::copy(&file.path, &draft)
+        .with_context(|| format!("Couldn't copy {}", file.path.display()))?;
+    let result = loop {
+        let status = std::process::Command::new(program)
+            .args(editor_args.clone())
+            .arg(&draft)
+            .status()
+            .with_context(|| format!("Couldn't run {editor}"))?;
+        if !status.success() {
+            break Err(anyhow::anyhow!("{editor} exited with {status}"));
+        }
 
-    let download_url = exe_asset["browser_download_url"]
-        .as_str()
-        .context("No download URL was passed")?;
+        let contents = std::fs::read_to_string(&draft)?;
+        match config_handler::check(&file.path, &contents).and_then(|config| check_runner(&config))
+        {
+            Ok(()) => {
+                break with_manifest(&install_path, || {
+                    manifest::write(&file.path, contents.as_bytes(), FileKind::Resource)
+                })
+            }
+            Err(e) => {
+                eprintln!("{} {e:#}", console::style("✘").red());
+                if !dialoguer::Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Edit again? Otherwise your changes are discarded")
+                    .default(true)
+                    .interact()?
+                {
+                    break Err(anyhow::anyhow!("{} left unchanged", file.path.display()));
+                }
+            }
+        }
+    };
+    std::fs::remove_file(&draft).ok();
+    result?;
+
+    println!(
+        "{} Saved '{}'",
+        console::style("✔").green(),
+        console::style(file.path.display())
+            .bold()
+            .underlined()
+            .white()
+    );
+    Ok(())
+}
 
-    let size = exe_asset["size"].as_u64().unwrap_or(0);
+/// Make sure a Steam install's `runner` names a Proton version that's actually installed
+fn check_runner(config: &Config) -> Result<()> {
+    let (InstallType::Steam, Some(runner), Some(steam_dir)) =
+        (config.install_type, &config.runner, &config.steam_dir)
+    else {
+        return Ok(());
+    };
+    let versions = gamelib_helper::steam_proton::find_all_versions(
+        steamlocate::SteamDir::from_dir(steam_dir)?,
+    )?;
+    if !versions.iter().any(|version| version.name == *runner) {
+        bail!(
+            "No Proton version named '{runner}' is installed. Installed: {}",
+            versions
+                .iter()
+                .map(|version| version.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn download_asset(
+    source: &ReleaseSource,
+    loader: &dyn ModLoader,
+    release: &serde_json::Value,
+    destination: &Path,
+) -> Result<PathBuf> {
+    let asset = source.installer_asset(loader, release)?;
 
-    let pb = ProgressBar::new(size);
+    let pb = ProgressBar::new(asset.size);
     pb.set_style(ProgressStyle::with_template("{spinner:.green} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
         .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
         .progress_chars("#>-"));
-    pb.set_message(format!("Downloading {}", exe_asset["name"]));
+    pb.set_message(format!("Downloading {}", asset.name));
 
-    std::fs::create_dir_all(&destination)?;
-    let file_name = exe_asset["name"]
-        .as_str()
-        .context("Invalid destination file name")?;
-    let file_path = destination.join(file_name);
-
-    let mut response = client.get(download_url).send()?;
-    let mut file = File::create(&file_path)?;
-    let mut writer = pb.wrap_write(&mut file);
-    let downloaded = response.copy_to(&mut writer)?;
-    pb.set_position(downloaded);
+    let file_path = downloader::download(source.client(), &asset, destination, &pb)?;
     pb.finish_and_clear();
     pb.println(format!("{} Download complete", console::style("✔").green()));
 
     Ok(file_path)
 }
 
-fn get_install_path() -> Result<PathBuf> {
+fn get_install_path(loader: &dyn ModLoader) -> Result<PathBuf> {
+    if let Some(path) = answers::get().get_str("install_path")? {
+        let path = PathBuf::from(path);
+        std::fs::create_dir_all(&path)
+            .with_context(|| format!("Couldn't create directory '{}'", path.display()))?;
+        println!(
+            "{} Installing to '{}'",
+            console::style("!").yellow(),
+            console::style(path.display()).bold().underlined().white()
+        );
+        return Ok(path);
+    }
+    let install_root = config_handler::user().install_root.as_ref();
+    if !answers::get().is_interactive() {
+        let Some(root) = install_root else {
+            return Err(answers::missing("install_path"));
+        };
+        let path = root.join(loader.name());
+        std::fs::create_dir_all(&path)
+            .with_context(|| format!("Couldn't create directory '{}'", path.display()))?;
+        return Ok(path);
+    }
+
     let term = console::Term::stdout();
     println!(
-        "{} Select a destination for 7th Heaven.",
-        console::style("+").yellow()
+        "{} Select a destination for {}.",
+        console::style("+").yellow(),
+        loader.name()
     );
 
     loop {
-        let install_path = FileDialog::new()
-            .set_title("Select Destination")
-            .pick_folder();
+        let mut dialog = FileDialog::new().set_title("Select Destination");
+        if let Some(root) = install_root {
+            dialog = dialog.set_directory(root);
+        }
+        let install_path = dialog.pick_folder();
 
         if let Some(path) = install_path {
-            let path = path.join("7th Heaven");
+            let path = path.join(loader.name());
             let choices = &["Yes", "No"];
             let confirm = dialoguer::Select::with_theme(&ColorfulTheme::default())
                 .with_prompt(format!(
-                    "Do you want to install 7th Heaven to '{}'?",
+                    "Do you want to install {} to '{}'?",
+                    loader.name(),
                     console::style(path.display()).bold().underlined()
                 ))
                 .default(0) // Default to "Yes"
@@ -423,172 +1782,30 @@ fn get_install_path() -> Result<PathBuf> {
     }
 }
 
-fn install_7th(
-    game: &dyn PrefixedGame,
-    exe_path: PathBuf,
-    install_path: &Path,
-    log_file: &str,
-) -> Result<()> {
-    let exe_path = exe_path
-        .canonicalize()
-        .with_context(|| format!("Installer not found at {:?}", exe_path))?;
-
-    if let Some(runner) = game.runner() {
-        log::info!("Using runner: {} ({})", runner.pretty_name, runner.name);
-    } else {
-        log::warn!("No runner set on detected game");
-    }
-    log::info!("Installer path: {}", exe_path.display());
-    log::info!("Game prefix: {}", game.prefix().display());
-
-    let args: Vec<String> = vec![
-        "/VERYSILENT".to_string(),
-        format!(
-            "/DIR=Z:{}",
-            install_path.to_string_lossy().replace('/', "\\")
-        ),
-        format!("/LOG={}", log_file),
-    ];
-
-    game.run_in_prefix(exe_path, Some(args))
-        .context("Couldn't run 7th Heaven installer")?;
-
-    let current_bin = env::current_exe().context("Failed to get binary path")?;
-    let current_dir = current_bin
-        .parent()
-        .context("Failed to get binary directory")?;
-    let toml_path = current_dir.join("MateriaForge.toml");
-    std::fs::copy(toml_path, install_path.join("MateriaForge.toml"))
-        .context("Failed to copy TOML to install_path")?;
-
-    let launcher_path = if cfg!(debug_assertions) {
-        "target/debug/launcher"
-    } else {
-        "launcher"
-    };
-
-    let shortcut_identifier = match game.app_id() {
-        FF7_APPID => "(2013)",
-        FF7_2026_APPID => "(2026)",
-        FF7_GOG_APPID => "(GOG)",
-        _ => "(Unknown)",
-    };
-
-    std::fs::copy(
-        launcher_path,
-        install_path.join(format!("Launch 7th Heaven {}", shortcut_identifier)),
-    )
-    .expect("Failed to copy launcher to install_path");
-
-    Ok(())
-}
-
-fn patch_install(install_path: &Path, game: &dyn PrefixedGame, update_channel: &str) -> Result<()> {
-    // Send timeout.exe to system32
-    let timeout_exe = resource_handler::as_bytes(
-        "timeout.exe".to_string(),
-        game.prefix().join("drive_c/windows/system32"),
-        resource_handler::TIMEOUT_EXE,
-    );
-    timeout_exe.write_if_missing()?;
-
-    // Patch settings.xml and send to install_path
-    let mut settings_xml = resource_handler::as_str(
-        "settings.xml".to_string(),
-        install_path.join("7thWorkshop"),
-        resource_handler::SETTINGS_XML,
-    );
-
-    let ff7_version = match game.app_id() {
-        FF7_APPID => "Steam",
-        FF7_2026_APPID => "SteamReRelease",
-        FF7_GOG_APPID => "GOG",
-        _ => "Unknown",
-    };
-    let library_location = &format!(
-        "Z:{}",
-        install_path
-            .join("mods")
-            .to_str()
-            .unwrap()
-            .replace("/", "\\")
-    );
-    let ff7_exe = match game.app_id() {
-        FF7_APPID => "ff7_en.exe",
-        FF7_2026_APPID => "FFVII.exe",
-        FF7_GOG_APPID => "FFVII.exe",
-        _ => "FFVII.exe",
-    };
-    let ff7_exe_path = &match game.app_id() {
-        FF7_GOG_APPID => {
-            format!(
-                "Z:{}",
-                game.path()
-                    .join(ff7_exe)
-                    .to_string_lossy()
-                    .replace("/", "\\")
-            )
-        }
-        _ => {
-            let full = game.path().join(ff7_exe).to_string_lossy().to_string();
-            let trimmed = full
-                .find("/steamapps/")
-                .map_or(full.as_str(), |i| &full[i..]);
-            format!("S:{}", trimmed.replace("/", "\\"))
-        }
-    };
-
-    settings_xml.contents = settings_xml
-        .contents
-        .replace("LIBRARY_LOCATION", library_location)
-        .replace("FF7_EXE", ff7_exe_path)
-        .replace("FF7_VERSION", ff7_version)
-        .replace("UPDATE_CHANNEL", update_channel);
-
-    settings_xml.write()?;
-
-    // Send dxvk.conf to install_path
-    let dxvk_conf = resource_handler::as_str(
-        "dxvk.conf".to_string(),
-        install_path.to_path_buf(),
-        resource_handler::DXVK_CONF,
-    );
-    dxvk_conf.write()?;
-
-    Ok(())
-}
-
 fn create_shortcuts(
     install_path: &Path,
     steam_dir: Option<steamlocate::SteamDir>,
-    app_id: u32,
-) -> Result<(bool, ())> {
+    steam_users: &mut Option<Vec<SteamUser>>,
+    edition: &Edition,
+    loader: &dyn ModLoader,
+) -> Result<Option<Shortcut>> {
     // App launcher shortcut
     let applications_dir = xdg::BaseDirectories::new()
         .get_data_home()
         .context("Couldn't get xdg_data_home")?
         .join("applications");
 
-    let shortcut_identifier = match app_id {
-        FF7_APPID => "(2013)",
-        FF7_2026_APPID => "(2026)",
-        FF7_GOG_APPID => "(GOG)",
-        _ => "(Unknown)",
-    };
-
     let mut shortcut_file = resource_handler::as_str(
-        format!("7th Heaven {}.desktop", shortcut_identifier),
+        format!("{} {}.desktop", loader.name(), edition.suffix),
         applications_dir,
-        resource_handler::SHORTCUT_FILE,
+        loader.shortcut_template(),
     );
 
     shortcut_file.contents = shortcut_file
         .contents
         .replace("INSTALL_PATH", &install_path.to_string_lossy());
 
-    shortcut_file.contents = shortcut_file
-        .contents
-        .replace("(VER)", &shortcut_identifier);
+    shortcut_file.contents = shortcut_file.contents.replace("(VER)", &edition.suffix);
 
     shortcut_file.write()?;
 
@@ -596,11 +1813,8 @@ fn create_shortcuts(
     let xdg_cache = xdg::BaseDirectories::new()
         .get_cache_home()
         .context("Couldn't get cache_home")?;
-    let logo_png = resource_handler::as_bytes(
-        "7th-heaven.png".to_string(),
-        xdg_cache,
-        resource_handler::LOGO_PNG,
-    );
+    let (icon_name, icon) = loader.icon();
+    let logo_png = resource_handler::as_bytes(icon_name.to_string(), xdg_cache, icon);
     logo_png.write()?;
     std::process::Command::new("xdg-icon-resource")
         .args([
@@ -612,91 +1826,132 @@ fn create_shortcuts(
         ])
         .spawn()?
         .wait()?;
+    manifest::record(Change::Icon {
+        name: Path::new(icon_name)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        size: 64,
+    });
 
     // Desktop shortcut
     let term = console::Term::stdout();
-    let choices = &["Yes", "No"];
-    let confirm = dialoguer::Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Do you want to add a shortcut to the Desktop?")
-        .default(0) // Default to "Yes"
-        .items(choices)
-        .interact()?;
-    match confirm {
-        0 => {
-            term.clear_last_lines(1)?;
-            let desktop_dir = home::home_dir()
-                .context("Couldn't get $HOME?")?
-                .join("Desktop");
-            println!("{} Adding Desktop shortcut.", console::style("!").yellow());
-            let desktop_shortcut_path = desktop_dir.join(&shortcut_file.name);
-            shortcut_file.write_to(&desktop_shortcut_path)?;
-        }
-        _ => {
-            term.clear_last_lines(1)?;
-        }
+    let (confirm, prompted) = answers::confirm_prompted(
+        "desktop_shortcut",
+        "Do you want to add a shortcut to the Desktop?",
+    )?;
+    if prompted {
+        term.clear_last_lines(1)?;
+    }
+    if confirm {
+        let desktop_dir = home::home_dir()
+            .context("Couldn't get $HOME?")?
+            .join("Desktop");
+        println!("{} Adding Desktop shortcut.", console::style("!").yellow());
+        let desktop_shortcut_path = desktop_dir.join(&shortcut_file.name);
+        shortcut_file.write_to(&desktop_shortcut_path)?;
     }
 
     // Non-Steam Game
-    let mut steam_shortcut = false;
+    let mut steam_shortcut = None;
     if let Some(dir) = steam_dir {
-        let choices = &["Yes", "No"];
-        let confirm = dialoguer::Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("Do you want to add a shortcut to Steam?")
-            .default(0) // Default to "Yes"
-            .items(choices)
-            .interact()?;
-        match confirm {
-            0 => {
-                steam_shortcut = true;
-                term.clear_last_lines(1)?;
-                println!("{} Adding Steam shortcut.", console::style("!").yellow());
-                gamelib_helper::steam_lib::add_nonsteam_game(
-                    &install_path.join(format!("Launch 7th Heaven {}", shortcut_identifier)),
-                    dir,
-                )?;
-            }
-            _ => {
-                term.clear_last_lines(1)?;
+        let (confirm, prompted) =
+            answers::confirm_prompted("steam_shortcut", "Do you want to add a shortcut to Steam?")?;
+        if prompted {
+            term.clear_last_lines(1)?;
+        }
+        if confirm {
+            let users = select_steam_users(dir.path(), steam_users)?;
+            println!("{} Adding Steam shortcut.", console::style("!").yellow());
+            let artwork = answers::get().get_str("artwork")?.map(PathBuf::from);
+            let custom_icon = artwork
+                .as_deref()
+                .and_then(|dir| artwork::custom(dir, artwork::Kind::Icon));
+            let shortcut = Shortcut {
+                app_name: loader.launcher_name(edition),
+                exe: install_path.join(loader.launcher_name(edition)),
+                start_dir: install_path.to_path_buf(),
+                icon: Some(custom_icon.unwrap_or(logo_png.destination.clone())),
+                launch_options: String::new(),
+                tags: Vec::new(),
+            };
+            gamelib_helper::steam_lib::add_nonsteam_game(dir.path(), users, &shortcut)?;
+            manifest::record(Change::SteamShortcut {
+                steam_dir: dir.path().to_path_buf(),
+                exe: shortcut.exe.clone(),
+            });
+
+            for user in users {
+                let app_id =
+                    gamelib_helper::steam_lib::nonsteam_app_id(dir.path(), user, &shortcut)?;
+                // The launcher is native, so a Proton version forced on the shortcut breaks it
+                if let Some(previous) = gamelib_helper::steam_game::compat_tool(dir.path(), app_id)
+                {
+                    gamelib_helper::steam_game::set_compat_tool(dir.path(), app_id, None)?;
+                    manifest::record(Change::CompatTool {
+                        steam_dir: dir.path().to_path_buf(),
+                        app_id,
+                        previous: Some(previous),
+                    });
+                }
+
+                let user = std::slice::from_ref(user);
+                let default = |kind| loader.artwork(kind);
+                artwork::install(dir.path(), user, app_id, default, artwork.as_deref())
+                    .context("Couldn't install Steam artwork")?;
             }
+            steam_shortcut = Some(shortcut);
         }
     }
 
-    Ok((steam_shortcut, ()))
+    Ok(steam_shortcut)
+}
+
+/// The Steam accounts to set things up for, asking the first time they're needed
+fn select_steam_users<'a>(
+    steam_dir: &Path,
+    steam_users: &'a mut Option<Vec<SteamUser>>,
+) -> Result<&'a [SteamUser]> {
+    if steam_users.is_none() {
+        *steam_users = Some(gamelib_helper::steam_lib::select_users(steam_dir)?);
+    }
+    Ok(steam_users.as_deref().unwrap_or_default())
 }
 
 fn add_controller_config(
-    game: &dyn PrefixedGame,
+    edition: &Edition,
     steam_dir: &Option<steamlocate::SteamDir>,
-    steam_shortcut: bool,
+    steam_users: &mut Option<Vec<SteamUser>>,
+    steam_shortcut: Option<&Shortcut>,
 ) -> Result<()> {
     if !*IS_DECK {
         log::info!("Not running on Steam Deck, skipping controller configuration.");
         return Ok(());
     }
-    if !steam_shortcut && game.app_id() == FF7_GOG_APPID {
+    if steam_shortcut.is_none() && edition.store == Store::Gog {
         log::info!("No Steam shortcut added for GOG version, skipping controller configuration.");
         return Ok(());
     }
 
     let term = console::Term::stdout();
     if let Some(dir) = steam_dir {
-        let choices = &["Yes", "No"];
-        let confirm = dialoguer::Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("Do you want to add a controller configuration to Steam?")
-            .default(0) // Default to "Yes"
-            .items(choices)
-            .interact()?;
+        let (confirm, prompted) = answers::confirm_prompted(
+            "controller_config",
+            "Do you want to add a controller configuration to Steam?",
+        )?;
+        if prompted {
+            term.clear_last_lines(1)?;
+        }
         match confirm {
-            0 => {
-                term.clear_last_lines(1)?;
+            true => {
                 println!(
                     "{} Adding controller configuration.",
                     console::style("!").yellow()
                 );
                 log::info!("Adding controller configuration for Steam Deck.");
             }
-            _ => {
-                term.clear_last_lines(1)?;
+            false => {
                 log::info!("User opted to skip adding controller configuration.");
                 return Ok(());
             }
@@ -708,7 +1963,13 @@ fn add_controller_config(
             resource_handler::CONTROLLER_PROFILE,
         );
         controller_vdf.write()?;
-        gamelib_helper::steam_lib::set_controller_config(dir, game.app_id(), steam_shortcut)?;
+        let users = select_steam_users(dir.path(), steam_users)?;
+        gamelib_helper::steam_lib::set_controller_config(
+            dir.path(),
+            users,
+            edition,
+            steam_shortcut,
+        )?;
     }
 
     Ok(())
@@ -0,0 +1,338 @@
+use crate::gamelib_helper::{steam_game, steam_lib};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+pub const MANIFEST_NAME: &str = "MateriaForge.manifest.json";
+
+/// The manifest of the install in progress, if one is being recorded
+static RECORDING: Mutex<Option<Manifest>> = Mutex::new(None);
+
+/// Every file an install wrote and every change it made to Steam, stored next to it so it can
+/// be audited, repaired or uninstalled
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub files: Vec<FileEntry>,
+    #[serde(default)]
+    pub changes: Vec<Change>,
+}
+
+/// A file written while installing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    /// SHA-256 of the contents MateriaForge wrote
+    pub sha256: String,
+    pub kind: FileKind,
+    /// Whether the file was there before MateriaForge wrote it
+    pub existed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    /// One of MateriaForge's embedded resources, e.g. `timeout.exe` or a `.desktop` file
+    Resource,
+    /// A file copied into the install, e.g. the launcher
+    Copy,
+    /// One of Steam's config files, edited in place
+    SteamConfig,
+}
+
+/// A change outside the install that takes more than deleting a file to undo
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// An icon installed with `xdg-icon-resource`
+    Icon { name: String, size: u32 },
+    /// A non-Steam game shortcut to `exe` in Steam's library
+    SteamShortcut { steam_dir: PathBuf, exe: PathBuf },
+    /// Controller template assignments in a Steam user's `configset_controller_neptune.vdf`,
+    /// with the template each key had before, if any
+    ControllerConfig {
+        path: PathBuf,
+        previous: BTreeMap<String, Option<String>>,
+    },
+    /// A file moved to `backup` so MateriaForge could put its own in its place
+    Backup { path: PathBuf, backup: PathBuf },
+    /// The Proton version Steam runs `app_id` with, and the one it used before
+    CompatTool {
+        steam_dir: PathBuf,
+        app_id: u32,
+        previous: Option<String>,
+    },
+}
+
+impl Manifest {
+    pub fn record(&mut self, change: Change) {
+        if !self.changes.contains(&change) {
+            self.changes.push(change);
+        }
+    }
+
+    /// Record a write to `path`. A file written more than once keeps its first `existed`.
+    pub fn record_file(&mut self, path: &Path, sha256: String, kind: FileKind, existed: bool) {
+        match self.files.iter_mut().find(|entry| entry.path == path) {
+            Some(entry) => entry.sha256 = sha256,
+            None => self.files.push(FileEntry {
+                path: path.to_path_buf(),
+                sha256,
+                kind,
+                existed,
+            }),
+        }
+    }
+
+    /// Add what was recorded in `other`, e.g. by an update of the same install
+    pub fn merge(&mut self, other: Manifest) {
+        for file in other.files {
+            self.record_file(&file.path, file.sha256, file.kind, file.existed);
+        }
+        for change in other.changes {
+            self.record(change);
+        }
+    }
+
+    /// The manifest of the install in `dir`; installs from before manifests have an empty one
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_NAME);
+        if !path.is_file() {
+            log::warn!("No {MANIFEST_NAME} in {}", dir.display());
+            return Ok(Manifest::default());
+        }
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Couldn't parse {}", path.display()))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_NAME);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Couldn't write {}", path.display()))
+    }
+}
+
+impl Change {
+    /// Revert the change
+    pub fn undo(&self) -> Result<()> {
+        match self {
+            Change::Icon { name, size } => {
+                let status = std::process::Command::new("xdg-icon-resource")
+                    .args(["uninstall", "--size", &size.to_string(), name])
+                    .status()
+                    .context("Couldn't run xdg-icon-resource")?;
+                if !status.success() {
+                    bail!("xdg-icon-resource exited with {status}");
+                }
+                Ok(())
+            }
+            Change::SteamShortcut { steam_dir, exe } => {
+                steam_lib::remove_nonsteam_game(steam_dir, exe)
+            }
+            Change::ControllerConfig { path, previous } => {
+                steam_lib::restore_controller_config(path, previous)
+            }
+            Change::Backup { path, backup } => {
+                if !backup.exists() {
+                    return Ok(());
+                }
+                std::fs::rename(backup, path)
+                    .with_context(|| format!("Couldn't restore {}", path.display()))
+            }
+            Change::CompatTool {
+                steam_dir,
+                app_id,
+                previous,
+            } => steam_game::set_compat_tool(steam_dir, *app_id, previous.as_deref()),
+        }
+    }
+
+    /// What undoing the change does, for the user
+    pub fn describe(&self) -> String {
+        match self {
+            Change::Icon { name, .. } => format!("Remove icon {name}"),
+            Change::SteamShortcut { exe, .. } => format!(
+                "Remove Steam shortcut '{}'",
+                exe.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            Change::ControllerConfig { path, .. } => {
+                format!("Restore controller configuration in {}", path.display())
+            }
+            Change::Backup { path, .. } => format!("Restore {}", path.display()),
+            Change::CompatTool {
+                app_id,
+                previous: Some(previous),
+                ..
+            } => format!("Restore Proton version {previous} for app {app_id}"),
+            Change::CompatTool { app_id, .. } => {
+                format!("Remove Proton version override for app {app_id}")
+            }
+        }
+    }
+}
+
+fn recording() -> MutexGuard<'static, Option<Manifest>> {
+    RECORDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Start recording writes and changes into a new manifest
+pub fn begin() {
+    *recording() = Some(Manifest::default());
+}
+
+/// Stop recording, returning everything recorded since [`begin`]
+pub fn finish() -> Manifest {
+    recording().take().unwrap_or_default()
+}
+
+/// Record while running `f`, then undo whatever changes are still recorded, latest first. `f`
+/// saves what it did with [`finish`] once there's an install to keep the manifest in, so the
+/// changes left are ones nothing could undo later, e.g. a Proton version set before a failed
+/// download.
+pub fn undo_unsaved<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    begin();
+    let result = f();
+    for change in finish().changes.iter().rev() {
+        if let Err(e) = change.undo() {
+            log::warn!("Couldn't undo '{}': {e:#}", change.describe());
+        }
+    }
+    result
+}
+
+/// Held by tests that record, since there's only one recording at a time
+#[cfg(test)]
+pub(crate) fn recording_lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Record `change` in the manifest being recorded, if any
+pub fn record(change: Change) {
+    if let Some(manifest) = recording().as_mut() {
+        manifest.record(change);
+    }
+}
+
+/// Record that `contents` were written to `path`, for writers that do their own IO
+pub fn record_write(path: &Path, contents: &[u8], kind: FileKind, existed: bool) {
+    if let Some(manifest) = recording().as_mut() {
+        manifest.record_file(path, hex::encode(Sha256::digest(contents)), kind, existed);
+    }
+}
+
+/// Write `contents` to `path`, recording it in the manifest
+pub fn write(path: &Path, contents: &[u8], kind: FileKind) -> Result<()> {
+    let existed = path.exists();
+    std::fs::write(path, contents).with_context(|| format!("Couldn't write to {path:?}"))?;
+    record_write(path, contents, kind, existed);
+    Ok(())
+}
+
+/// Copy `from` to `to`, recording the copy in the manifest
+pub fn copy(from: &Path, to: &Path) -> Result<()> {
+    let existed = to.exists();
+    std::fs::copy(from, to).with_context(|| format!("Couldn't copy {from:?} to {to:?}"))?;
+    let contents = std::fs::read(to).with_context(|| format!("Couldn't read {to:?}"))?;
+    record_write(to, &contents, FileKind::Copy, existed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Move `path` aside to `backup` and write `contents` in its place, the way artwork does
+    fn replace_with(path: &Path, backup: &str, contents: &str) {
+        let backup = path.with_extension(backup);
+        std::fs::rename(path, &backup).unwrap();
+        record(Change::Backup {
+            path: path.to_path_buf(),
+            backup,
+        });
+        write(path, contents.as_bytes(), FileKind::Resource).unwrap();
+    }
+
+    #[test]
+    fn undo_unsaved_undoes_latest_first() {
+        let _lock = recording_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let hero = dir.path().join("hero.png");
+        std::fs::write(&hero, "user's").unwrap();
+
+        // An install that fails before its manifest is saved
+        let result: Result<()> = undo_unsaved(|| {
+            replace_with(&hero, "bak1", "first");
+            replace_with(&hero, "bak2", "second");
+            bail!("Couldn't run 7th Heaven installer")
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&hero).unwrap(), "user's");
+        assert!(!hero.with_extension("bak1").exists());
+        assert!(!hero.with_extension("bak2").exists());
+        assert!(finish().changes.is_empty());
+
+        // One that saved its manifest next to the install keeps its changes
+        let saved = undo_unsaved(|| {
+            replace_with(&hero, "bak1", "installed");
+            Ok(finish())
+        })
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&hero).unwrap(), "installed");
+        assert_eq!(
+            std::fs::read_to_string(hero.with_extension("bak1")).unwrap(),
+            "user's"
+        );
+        assert_eq!(saved.changes.len(), 1);
+        // Written after the user's was moved aside, so uninstall removes it before restoring theirs
+        let entry = saved.files.iter().find(|file| file.path == hero).unwrap();
+        assert!(!entry.existed);
+    }
+
+    #[test]
+    fn merge_keeps_first_existed_and_dedupes_changes() {
+        let change = Change::Icon {
+            name: "7th-heaven".to_string(),
+            size: 64,
+        };
+        let mut manifest = Manifest::default();
+        manifest.record_file(Path::new("/a"), "1".to_string(), FileKind::Copy, false);
+        manifest.record(change.clone());
+
+        let mut update = Manifest::default();
+        update.record_file(Path::new("/a"), "2".to_string(), FileKind::Copy, true);
+        update.record(change.clone());
+        manifest.merge(update);
+
+        assert_eq!(
+            manifest.files,
+            [FileEntry {
+                path: PathBuf::from("/a"),
+                sha256: "2".to_string(),
+                kind: FileKind::Copy,
+                existed: false,
+            }]
+        );
+        assert_eq!(manifest.changes, [change]);
+    }
+
+    #[test]
+    fn undo_of_missing_backup_is_a_no_op() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hero.png");
+        std::fs::write(&path, "installed").unwrap();
+        let change = Change::Backup {
+            path: path.clone(),
+            backup: dir.path().join("hero.png.materiaforge-bak"),
+        };
+        change.undo().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "installed");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::editions::Edition;
+use crate::gamelib_helper::{artwork::Kind, Game};
+use crate::mod_loader::{z_drive_path, ModLoader};
+use crate::resource_handler;
+use std::path::Path;
+
+/// Junction VIII, the FF8 mod loader
+#[derive(Debug, Clone, Copy)]
+pub struct JunctionViii;
+
+impl ModLoader for JunctionViii {
+    fn name(&self) -> &str {
+        "Junction VIII"
+    }
+    fn id(&self) -> &str {
+        "junction_viii"
+    }
+    fn game(&self) -> &str {
+        "FF8"
+    }
+    fn matches_title(&self, title: &str) -> bool {
+        title.to_lowercase().contains("final fantasy viii")
+    }
+    fn repo(&self) -> &str {
+        "tsunamods-codes/Junction-VIII"
+    }
+    fn exe_name(&self) -> &str {
+        "Junction VIII.exe"
+    }
+    fn log_file(&self) -> &str {
+        "JunctionVIII.log"
+    }
+    fn workshop_dir(&self) -> &str {
+        "J8Workshop"
+    }
+    fn settings_template(&self) -> &str {
+        resource_handler::J8_SETTINGS_XML
+    }
+    fn exe_setting(&self) -> &'static str {
+        "FF8Exe"
+    }
+
+    fn settings_values(
+        &self,
+        edition: &Edition,
+        game: &dyn Game,
+        install_path: &Path,
+        update_channel: &str,
+    ) -> Vec<(&'static str, String)> {
+        vec![
+            ("LibraryLocation", z_drive_path(&install_path.join("mods"))),
+            (self.exe_setting(), edition.exe_path(game.path())),
+            ("FF8InstalledVersion", edition.installed_version.clone()),
+            ("FFNxUpdateChannel", update_channel.to_string()),
+            ("AppUpdateChannel", update_channel.to_string()),
+        ]
+    }
+
+    fn shortcut_template(&self) -> &str {
+        resource_handler::J8_SHORTCUT_FILE
+    }
+    fn icon(&self) -> (&str, &[u8]) {
+        ("junction-viii.png", resource_handler::J8_LOGO_PNG)
+    }
+    fn artwork(&self, kind: Kind) -> &'static [u8] {
+        match kind {
+            Kind::Grid => resource_handler::J8_GRID_PNG,
+            Kind::WideGrid => resource_handler::J8_WIDE_GRID_PNG,
+            Kind::Hero => resource_handler::J8_HERO_PNG,
+            Kind::Logo | Kind::Icon => resource_handler::J8_LOGO_PNG,
+        }
+    }
+}
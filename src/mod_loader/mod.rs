@@ -0,0 +1,448 @@
+use crate::config_handler::{self, Config};
+use crate::editions::Edition;
+use crate::gamelib_helper::{artwork, Game, PrefixedGame};
+use crate::manifest;
+use crate::resource_handler;
+use crate::settings_xml::Settings;
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+/// A mod loader MateriaForge can install, e.g. 7th Heaven for FF7.
+///
+/// The install pipeline ([`install`] and [`patch`]) only talks to loaders through this
+/// trait, so frontends can drive any loader the same way.
+pub trait ModLoader {
+    /// Display name, e.g. "7th Heaven"
+    fn name(&self) -> &str;
+    /// Value stored as `loader` in MateriaForge.toml
+    fn id(&self) -> &str;
+    /// Short name of the game this loader mods, e.g. "FF7"
+    fn game(&self) -> &str;
+    /// Whether a detected game title belongs to this loader's game
+    fn matches_title(&self, title: &str) -> bool;
+    /// GitHub repo releases are downloaded from
+    fn repo(&self) -> &str;
+    /// Whether a release asset is this loader's installer
+    fn is_installer_asset(&self, asset_name: &str) -> bool {
+        asset_name.ends_with(".exe")
+    }
+    /// The loader's executable, relative to the install path
+    fn exe_name(&self) -> &str;
+    /// Log file name passed to the installer
+    fn log_file(&self) -> &str;
+    /// Directory under the install path holding `settings.xml`
+    fn workshop_dir(&self) -> &str;
+    /// `settings.xml` for installs that don't have one yet
+    fn settings_template(&self) -> &str;
+    /// `settings.xml` element holding the path to the game's executable
+    fn exe_setting(&self) -> &'static str;
+    /// Values for the `settings.xml` elements MateriaForge manages, by element name
+    fn settings_values(
+        &self,
+        edition: &Edition,
+        game: &dyn Game,
+        install_path: &Path,
+        update_channel: &str,
+    ) -> Vec<(&'static str, String)>;
+    fn shortcut_template(&self) -> &str;
+    /// Icon file name (matching `Icon=` in the shortcut template) and contents
+    fn icon(&self) -> (&str, &[u8]);
+    /// Steam library image of `kind` for the shortcut when the user has none
+    fn artwork(&self, kind: artwork::Kind) -> &'static [u8];
+
+    /// File name of the launcher binary copied into the install path
+    fn launcher_name(&self, edition: &Edition) -> String {
+        format!("Launch {} {}", self.name(), edition.suffix)
+    }
+}
+
+/// Every supported mod loader
+pub fn all() -> Vec<Box<dyn ModLoader>> {
+    vec![
+        Box::new(seventh_heaven::SeventhHeaven),
+        Box::new(junction_viii::JunctionViii),
+    ]
+}
+
+/// Look up a loader by its [`ModLoader::id`]
+pub fn from_id(id: &str) -> Option<Box<dyn ModLoader>> {
+    all().into_iter().find(|loader| loader.id() == id)
+}
+
+/// Convert a Linux path to the Windows path Wine sees through the Z: drive
+pub fn z_drive_path(path: &Path) -> String {
+    format!("Z:{}", path.to_string_lossy().replace('/', "\\"))
+}
+
+/// Convert a path inside a Steam library to the path Proton exposes through the S: drive
+pub fn s_drive_path(path: &Path) -> String {
+    let full = path.to_string_lossy().to_string();
+    let trimmed = full
+        .find("/steamapps/")
+        .map_or(full.as_str(), |i| &full[i..]);
+    format!("S:{}", trimmed.replace('/', "\\"))
+}
+
+/// Run the loader's installer into `install_path` and copy the launcher next to it
+pub fn install(
+    loader: &dyn ModLoader,
+    edition: &Edition,
+    game: &dyn PrefixedGame,
+    exe_path: PathBuf,
+    install_path: &Path,
+    launcher_bin: &Path,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    run_installer(loader, game, exe_path, install_path, env)?;
+
+    let current_bin = env::current_exe().context("Failed to get binary path")?;
+    let current_dir = current_bin
+        .parent()
+        .context("Failed to get binary directory")?;
+    let toml_path = current_dir.join("MateriaForge.toml");
+    manifest::copy(&toml_path, &install_path.join("MateriaForge.toml"))
+        .context("Failed to copy TOML to install_path")?;
+
+    manifest::copy(
+        launcher_bin,
+        &install_path.join(loader.launcher_name(edition)),
+    )
+    .context("Failed to copy launcher to install_path")?;
+
+    Ok(())
+}
+
+/// Run a (usually older) installer over an existing installation, keeping the user's mods and
+/// loader settings as they are
+pub fn reinstall(
+    loader: &dyn ModLoader,
+    game: &dyn PrefixedGame,
+    exe_path: PathBuf,
+    install_path: &Path,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    let kept = ["mods", loader.workshop_dir()];
+    let name = install_path
+        .file_name()
+        .context("Install path has no directory name")?
+        .to_string_lossy();
+    let backup_dir = install_path.with_file_name(format!("{name}.materiaforge-keep"));
+    if backup_dir.exists() {
+        bail!(
+            "'{}' is left over from an interrupted reinstall, move its contents back into '{}' first",
+            backup_dir.display(),
+            install_path.display()
+        );
+    }
+    std::fs::create_dir_all(&backup_dir)
+        .with_context(|| format!("Couldn't create '{}'", backup_dir.display()))?;
+    for dir in kept {
+        let path = install_path.join(dir);
+        if path.exists() {
+            std::fs::rename(&path, backup_dir.join(dir))
+                .with_context(|| format!("Couldn't move '{}' aside", path.display()))?;
+        }
+    }
+
+    let result = run_installer(loader, game, exe_path, install_path, env);
+
+    // Put the kept directories back even if the installer failed
+    for dir in kept {
+        let saved = backup_dir.join(dir);
+        if !saved.exists() {
+            continue;
+        }
+        let path = install_path.join(dir);
+        if path.exists() {
+            std::fs::remove_dir_all(&path)
+                .with_context(|| format!("Couldn't remove '{}'", path.display()))?;
+        }
+        std::fs::rename(&saved, &path)
+            .with_context(|| format!("Couldn't restore '{}'", path.display()))?;
+    }
+    std::fs::remove_dir(&backup_dir).ok();
+
+    result
+}
+
+fn run_installer(
+    loader: &dyn ModLoader,
+    game: &dyn PrefixedGame,
+    exe_path: PathBuf,
+    install_path: &Path,
+    env: &BTreeMap<String, String>,
+) -> Result<()> {
+    let exe_path = exe_path
+        .canonicalize()
+        .with_context(|| format!("Installer not found at {:?}", exe_path))?;
+
+    if let Some(runner) = game.runner() {
+        log::info!("Using runner: {} ({})", runner.pretty_name, runner.name);
+    } else {
+        log::warn!("No runner set on detected game");
+    }
+    log::info!("Installer path: {}", exe_path.display());
+    log::info!("Game prefix: {}", game.prefix().display());
+
+    let args: Vec<String> = vec![
+        "/VERYSILENT".to_string(),
+        format!("/DIR={}", z_drive_path(install_path)),
+        format!("/LOG={}", loader.log_file()),
+    ];
+
+    game.run_in_prefix(exe_path, Some(args), env)
+        .with_context(|| format!("Couldn't run {} installer", loader.name()))
+}
+
+/// Apply prefix patches and write the loader's settings and `dxvk.conf` into `install_path`
+pub fn patch(
+    loader: &dyn ModLoader,
+    edition: &Edition,
+    game: &dyn PrefixedGame,
+    install_path: &Path,
+    update_channel: &str,
+) -> Result<()> {
+    patch_prefix(game)?;
+    write_settings(loader, edition, game, install_path, update_channel)?;
+    write_dxvk_conf(install_path)
+}
+
+/// Install a newer release over an existing installation and refresh what MateriaForge added to
+/// it: the launcher, prefix patches and the managed `settings.xml` values, and `dxvk.conf` if
+/// it's missing. The user's mods, profiles, other settings and `dxvk.conf` edits are kept.
+pub fn update(
+    loader: &dyn ModLoader,
+    config: &Config,
+    game: &dyn PrefixedGame,
+    exe_path: PathBuf,
+    install_path: &Path,
+    launcher_bin: &Path,
+    update_channel: &str,
+) -> Result<()> {
+    let edition = &config.edition()?;
+    reinstall(loader, game, exe_path, install_path, &config.env)?;
+    manifest::copy(
+        launcher_bin,
+        &install_path.join(loader.launcher_name(edition)),
+    )
+    .context("Failed to copy launcher to install_path")?;
+
+    patch_prefix(game)?;
+    write_settings(loader, edition, game, install_path, update_channel)?;
+    write_dxvk_conf(install_path)
+}
+
+/// Copy `timeout.exe` into the prefix, which the loader needs and Wine lacks
+pub fn patch_prefix(game: &dyn PrefixedGame) -> Result<()> {
+    // Send timeout.exe to system32
+    let timeout_exe = resource_handler::as_bytes(
+        "timeout.exe".to_string(),
+        game.prefix().join("drive_c/windows/system32"),
+        resource_handler::TIMEOUT_EXE,
+    );
+    timeout_exe.write_if_missing()?;
+    Ok(())
+}
+
+/// Set the values MateriaForge manages in the loader's `settings.xml`, starting from the
+/// template if there isn't one yet, and subscribe it to the user's catalogs
+pub fn write_settings(
+    loader: &dyn ModLoader,
+    edition: &Edition,
+    game: &dyn PrefixedGame,
+    install_path: &Path,
+    update_channel: &str,
+) -> Result<()> {
+    let mut settings = Settings::load_or(
+        &settings_path(loader, install_path),
+        loader.settings_template(),
+    )?;
+    for (element, value) in loader.settings_values(edition, game, install_path, update_channel) {
+        settings.set(element, &value)?;
+    }
+    for catalog in &config_handler::user().catalogs {
+        settings.add_catalog(catalog)?;
+    }
+    settings.save()
+}
+
+/// Point the loader's existing `settings.xml` at another update channel, e.g. "Canary"
+pub fn set_update_channel(
+    loader: &dyn ModLoader,
+    install_path: &Path,
+    update_channel: &str,
+) -> Result<()> {
+    let mut settings = Settings::load(&settings_path(loader, install_path))?;
+    settings.set("FFNxUpdateChannel", update_channel)?;
+    settings.set("AppUpdateChannel", update_channel)?;
+    settings.save()
+}
+
+pub fn settings_path(loader: &dyn ModLoader, install_path: &Path) -> PathBuf {
+    install_path
+        .join(loader.workshop_dir())
+        .join("settings.xml")
+}
+
+/// Write the default `dxvk.conf` into `install_path`, keeping one the user already has
+pub fn write_dxvk_conf(install_path: &Path) -> Result<()> {
+    // Send dxvk.conf to install_path
+    let dxvk_conf = resource_handler::as_bytes(
+        "dxvk.conf".to_string(),
+        install_path.to_path_buf(),
+        resource_handler::DXVK_CONF.as_bytes(),
+    );
+    dxvk_conf.write_if_missing()?;
+    Ok(())
+}
+
+pub mod junction_viii;
+pub mod seventh_heaven;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamelib_helper::{PrefixRunner, Runner};
+    use seventh_heaven::SeventhHeaven;
+
+    /// A game whose "installer" lays out a fresh install in `install_path`, or fails
+    struct FakeGame {
+        prefix: PathBuf,
+        install_path: PathBuf,
+        fail: bool,
+    }
+
+    impl Game for FakeGame {
+        fn app_id(&self) -> u32 {
+            39140
+        }
+        fn name(&self) -> &str {
+            "FINAL FANTASY VII"
+        }
+        fn path(&self) -> &Path {
+            &self.prefix
+        }
+        fn prefix(&self) -> &Path {
+            &self.prefix
+        }
+        fn runner(&self) -> Option<&Runner> {
+            None
+        }
+    }
+
+    impl PrefixRunner for FakeGame {
+        fn run_in_prefix(
+            &self,
+            _exe_to_launch: PathBuf,
+            _args: Option<Vec<String>>,
+            _env: &BTreeMap<String, String>,
+        ) -> Result<()> {
+            // The kept directories are out of the installer's way
+            assert!(!self.install_path.join("mods").exists());
+            std::fs::create_dir_all(self.install_path.join("mods/installer")).unwrap();
+            std::fs::create_dir_all(self.install_path.join("7thWorkshop")).unwrap();
+            std::fs::write(
+                self.install_path.join("7thWorkshop/settings.xml"),
+                "default",
+            )
+            .unwrap();
+            std::fs::write(self.install_path.join("7th Heaven.exe"), "older").unwrap();
+            match self.fail {
+                true => bail!("Installer exited with 1"),
+                false => Ok(()),
+            }
+        }
+    }
+
+    /// An install with a mod and edited settings, plus an installer to run over it
+    fn install(dir: &Path, fail: bool) -> (FakeGame, PathBuf) {
+        let install_path = dir.join("7th Heaven");
+        std::fs::create_dir_all(install_path.join("mods")).unwrap();
+        std::fs::create_dir_all(install_path.join("7thWorkshop/profiles")).unwrap();
+        std::fs::write(install_path.join("mods/finishing-touch.iro"), "mod").unwrap();
+        std::fs::write(install_path.join("7thWorkshop/settings.xml"), "mine").unwrap();
+        std::fs::write(install_path.join("7th Heaven.exe"), "newer").unwrap();
+        let installer = dir.join("7thHeaven-v4.2.0.exe");
+        std::fs::write(&installer, "MZ").unwrap();
+        let game = FakeGame {
+            prefix: dir.join("pfx"),
+            install_path,
+            fail,
+        };
+        (game, installer)
+    }
+
+    fn assert_kept(install_path: &Path) {
+        assert!(install_path.join("mods/finishing-touch.iro").is_file());
+        assert!(!install_path.join("mods/installer").exists());
+        let settings = std::fs::read_to_string(install_path.join("7thWorkshop/settings.xml"));
+        assert_eq!(settings.unwrap(), "mine");
+        assert!(install_path.join("7thWorkshop/profiles").is_dir());
+        assert!(!install_path
+            .with_file_name("7th Heaven.materiaforge-keep")
+            .exists());
+    }
+
+    #[test]
+    fn reinstall_keeps_mods_and_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let (game, installer) = install(dir.path(), false);
+        let install_path = game.install_path.clone();
+
+        reinstall(
+            &SeventhHeaven,
+            &game,
+            installer,
+            &install_path,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert_kept(&install_path);
+        let exe = std::fs::read_to_string(install_path.join("7th Heaven.exe"));
+        assert_eq!(exe.unwrap(), "older");
+    }
+
+    #[test]
+    fn reinstall_restores_mods_when_the_installer_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let (game, installer) = install(dir.path(), true);
+        let install_path = game.install_path.clone();
+
+        let result = reinstall(
+            &SeventhHeaven,
+            &game,
+            installer,
+            &install_path,
+            &BTreeMap::new(),
+        );
+        assert!(format!("{:#}", result.unwrap_err()).contains("Installer exited with 1"));
+        assert_kept(&install_path);
+    }
+
+    #[test]
+    fn reinstall_refuses_leftover_keep_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let (game, installer) = install(dir.path(), false);
+        let install_path = game.install_path.clone();
+        let leftover = dir.path().join("7th Heaven.materiaforge-keep/mods");
+        std::fs::create_dir_all(&leftover).unwrap();
+
+        let result = reinstall(
+            &SeventhHeaven,
+            &game,
+            installer,
+            &install_path,
+            &BTreeMap::new(),
+        );
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("interrupted reinstall"));
+        assert!(install_path.join("mods/finishing-touch.iro").is_file());
+        assert!(leftover.is_dir());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::editions::Edition;
+use crate::gamelib_helper::{artwork::Kind, Game};
+use crate::mod_loader::{z_drive_path, ModLoader};
+use crate::resource_handler;
+use std::path::Path;
+
+/// 7th Heaven, the FF7 mod loader
+#[derive(Debug, Clone, Copy)]
+pub struct SeventhHeaven;
+
+impl ModLoader for SeventhHeaven {
+    fn name(&self) -> &str {
+        "7th Heaven"
+    }
+    fn id(&self) -> &str {
+        "7th_heaven"
+    }
+    fn game(&self) -> &str {
+        "FF7"
+    }
+    fn matches_title(&self, title: &str) -> bool {
+        let title = title.to_lowercase();
+        title.contains("final fantasy vii") && !title.contains("final fantasy viii")
+    }
+    fn repo(&self) -> &str {
+        "tsunamods-codes/7th-Heaven"
+    }
+    fn exe_name(&self) -> &str {
+        "7th Heaven.exe"
+    }
+    fn log_file(&self) -> &str {
+        "7thHeaven.log"
+    }
+    fn workshop_dir(&self) -> &str {
+        "7thWorkshop"
+    }
+    fn settings_template(&self) -> &str {
+        resource_handler::SETTINGS_XML
+    }
+    fn exe_setting(&self) -> &'static str {
+        "FF7Exe"
+    }
+
+    fn settings_values(
+        &self,
+        edition: &Edition,
+        game: &dyn Game,
+        install_path: &Path,
+        update_channel: &str,
+    ) -> Vec<(&'static str, String)> {
+        vec![
+            ("LibraryLocation", z_drive_path(&install_path.join("mods"))),
+            (self.exe_setting(), edition.exe_path(game.path())),
+            ("FF7InstalledVersion", edition.installed_version.clone()),
+            ("FFNxUpdateChannel", update_channel.to_string()),
+            ("AppUpdateChannel", update_channel.to_string()),
+        ]
+    }
+
+    fn shortcut_template(&self) -> &str {
+        resource_handler::SHORTCUT_FILE
+    }
+    fn icon(&self) -> (&str, &[u8]) {
+        ("7th-heaven.png", resource_handler::LOGO_PNG)
+    }
+    fn artwork(&self, kind: Kind) -> &'static [u8] {
+        match kind {
+            Kind::Grid => resource_handler::GRID_PNG,
+            Kind::WideGrid => resource_handler::WIDE_GRID_PNG,
+            Kind::Hero => resource_handler::HERO_PNG,
+            Kind::Logo | Kind::Icon => resource_handler::LOGO_PNG,
+        }
+    }
+}
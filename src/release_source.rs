@@ -0,0 +1,329 @@
+use crate::answers::{self, Answers};
+use crate::config_handler::{self, UserConfig};
+use crate::downloader::Asset;
+use crate::mod_loader::ModLoader;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use reqwest::{
+    blocking::Client,
+    header::{HeaderValue, AUTHORIZATION},
+    StatusCode,
+};
+use serde_json::Value;
+
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+const USER_AGENT: &str = concat!("MateriaForge/", env!("CARGO_PKG_VERSION"));
+
+/// Where a loader's releases come from: GitHub, or a Gitea/Forgejo mirror with the same
+/// `/repos/{owner}/{repo}/releases` API
+#[derive(Debug, Clone)]
+pub struct ReleaseSource {
+    /// API root, e.g. `https://api.github.com` or `https://git.example.com/api/v1`
+    pub api_url: String,
+    /// `owner/name` of the repo on that server
+    pub repo: String,
+    /// Regex picking the installer among a release's assets, instead of the loader's default
+    pub asset_pattern: Option<Regex>,
+    /// `Authorization` header for requests to `api_url`; never sent to asset download hosts
+    authorization: Option<HeaderValue>,
+    client: Client,
+}
+
+impl ReleaseSource {
+    /// `token` is sent to `api_url` as a bearer token, which raises GitHub's rate limit and
+    /// allows private mirrors
+    pub fn new(
+        api_url: &str,
+        repo: &str,
+        token: Option<String>,
+        asset_pattern: Option<&str>,
+    ) -> Result<Self> {
+        let asset_pattern = asset_pattern
+            .map(Regex::new)
+            .transpose()
+            .context("Invalid asset pattern")?;
+
+        let authorization = token
+            .map(|token| {
+                let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+                    .context("Invalid characters in token")?;
+                value.set_sensitive(true);
+                anyhow::Ok(value)
+            })
+            .transpose()?;
+        let client = Client::builder().user_agent(USER_AGENT).build()?;
+
+        Ok(ReleaseSource {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            repo: repo.to_string(),
+            asset_pattern,
+            authorization,
+            client,
+        })
+    }
+
+    /// The source for `loader`: the `api_url`, `repo`, `asset_pattern` and `token` answers,
+    /// then the user's `[release.<loader id>]` table. `$GITHUB_TOKEN` is only used for GitHub
+    /// itself, so a mirror never receives it.
+    pub fn for_loader(loader: &dyn ModLoader) -> Result<Self> {
+        Self::configured(
+            loader,
+            answers::get(),
+            config_handler::user(),
+            std::env::var("GITHUB_TOKEN").ok(),
+        )
+    }
+
+    fn configured(
+        loader: &dyn ModLoader,
+        answers: &Answers,
+        user: &UserConfig,
+        github_token: Option<String>,
+    ) -> Result<Self> {
+        let user = user.release.get(loader.id()).cloned().unwrap_or_default();
+        let api_url = answers
+            .get_str("api_url")?
+            .or(user.api_url)
+            .unwrap_or_else(|| DEFAULT_API_URL.to_string());
+        let repo = answers
+            .get_str("repo")?
+            .or(user.repo)
+            .unwrap_or_else(|| loader.repo().to_string());
+        let asset_pattern = answers.get_str("asset_pattern")?.or(user.asset_pattern);
+        let github_token = github_token
+            .filter(|token| !token.is_empty())
+            .filter(|_| api_url.trim_end_matches('/') == DEFAULT_API_URL);
+        let token = answers.get_str("token")?.or(user.token).or(github_token);
+        ReleaseSource::new(&api_url, &repo, token, asset_pattern.as_deref())
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn get(&self, path: &str) -> Result<reqwest::blocking::Response> {
+        let url = format!("{}/repos/{}/{path}", self.api_url, self.repo);
+        log::info!("Fetching {url}");
+        let mut request = self.client.get(&url);
+        if let Some(authorization) = &self.authorization {
+            request = request.header(AUTHORIZATION, authorization.clone());
+        }
+        let response = request.send()?;
+        if response.status() == StatusCode::FORBIDDEN
+            || response.status() == StatusCode::TOO_MANY_REQUESTS
+        {
+            bail!(
+                "{} refused the request (HTTP {}); if you're rate limited, set GITHUB_TOKEN",
+                self.api_url,
+                response.status()
+            );
+        }
+        Ok(response)
+    }
+
+    /// Every release, newest first
+    pub fn releases(&self) -> Result<Vec<Value>> {
+        Ok(self.get("releases")?.error_for_status()?.json()?)
+    }
+
+    /// The release tagged `tag`, or else the newest release of any kind (for canary) or the
+    /// latest stable release
+    pub fn release(&self, prerelease: bool, tag: Option<&str>) -> Result<Value> {
+        if let Some(tag) = tag {
+            let response = self.get(&format!("releases/tags/{tag}"))?;
+            if response.status() == StatusCode::NOT_FOUND {
+                bail!("No release tagged '{tag}' in {}", self.repo);
+            }
+            Ok(response.error_for_status()?.json()?)
+        } else if prerelease {
+            self.releases()?
+                .into_iter()
+                .next()
+                .with_context(|| format!("No releases found in {}", self.repo))
+        } else {
+            Ok(self.get("releases/latest")?.error_for_status()?.json()?)
+        }
+    }
+
+    pub fn is_installer_asset(&self, loader: &dyn ModLoader, name: &str) -> bool {
+        match &self.asset_pattern {
+            Some(pattern) => pattern.is_match(name),
+            None => loader.is_installer_asset(name),
+        }
+    }
+
+    /// The loader's installer among a release's assets
+    pub fn installer_asset(&self, loader: &dyn ModLoader, release: &Value) -> Result<Asset> {
+        let assets = release["assets"]
+            .as_array()
+            .context("No assets found in release")?;
+        let asset = assets
+            .iter()
+            .find(|a| self.is_installer_asset(loader, a["name"].as_str().unwrap_or("")))
+            .with_context(|| match &self.asset_pattern {
+                Some(pattern) => format!("No asset matching '{pattern}' in release"),
+                None => "No .exe asset found".to_string(),
+            })?;
+        Asset::from_json(asset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_handler::ReleaseConfig;
+    use crate::downloader;
+    use crate::mod_loader::{junction_viii::JunctionViii, seventh_heaven::SeventhHeaven};
+    use crate::test_server::{Response, Server};
+    use serde_json::json;
+
+    #[test]
+    fn api_url_and_repo_override() {
+        let server = Server::start(|_| Response::ok(r#"{"tag_name": "4.3.0"}"#));
+        let source = ReleaseSource::new(
+            &format!("{}/api/v1/", server.url),
+            "mirror/7th-Heaven",
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(source.release(false, None).unwrap()["tag_name"], "4.3.0");
+        source.release(false, Some("4.2.0")).unwrap();
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/api/v1/repos/mirror/7th-Heaven/releases/latest",
+                "/api/v1/repos/mirror/7th-Heaven/releases/tags/4.2.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn token_is_only_sent_to_the_api() {
+        let installer = b"MZ installer".to_vec();
+        let size = installer.len();
+        let downloads = Server::start(move |_| Response::ok(installer.clone()));
+        let release = json!({
+            "tag_name": "4.3.0",
+            "assets": [{
+                "name": "7thHeaven-v4.3.0.exe",
+                "browser_download_url": format!("{}/7thHeaven-v4.3.0.exe", downloads.url),
+                "size": size,
+            }],
+        });
+        let api = Server::start(move |_| Response::ok(release.to_string()));
+        let source =
+            ReleaseSource::new(&api.url, "owner/repo", Some("secret".to_string()), None).unwrap();
+
+        let release = source.release(false, None).unwrap();
+        let asset = source.installer_asset(&SeventhHeaven, &release).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = downloader::download(
+            source.client(),
+            &asset,
+            dir.path(),
+            &indicatif::ProgressBar::hidden(),
+        )
+        .unwrap();
+
+        assert_eq!(path.file_name().unwrap(), "7thHeaven-v4.3.0.exe");
+        assert_eq!(
+            api.requests()[0].header("authorization"),
+            Some("Bearer secret")
+        );
+        assert_eq!(downloads.requests()[0].header("authorization"), None);
+    }
+
+    #[test]
+    fn asset_pattern_picks_the_installer() {
+        let release = json!({
+            "assets": [
+                {"name": "FFNx.exe", "browser_download_url": "https://example.org/FFNx.exe"},
+                {"name": "7thHeaven-v4.3.0.exe", "browser_download_url": "https://example.org/7h.exe"},
+            ],
+        });
+        let source = |pattern| ReleaseSource::new(DEFAULT_API_URL, "owner/repo", None, pattern);
+
+        let asset = source(Some(r"^7thHeaven-.*\.exe$"))
+            .unwrap()
+            .installer_asset(&SeventhHeaven, &release)
+            .unwrap();
+        assert_eq!(asset.name, "7thHeaven-v4.3.0.exe");
+        // Without a pattern the loader's own rule picks the first .exe
+        let asset = source(None)
+            .unwrap()
+            .installer_asset(&SeventhHeaven, &release)
+            .unwrap();
+        assert_eq!(asset.name, "FFNx.exe");
+        let error = source(Some(r"\.zip$"))
+            .unwrap()
+            .installer_asset(&SeventhHeaven, &release)
+            .unwrap_err();
+        assert_eq!(error.to_string(), r"No asset matching '\.zip$' in release");
+        assert!(source(Some("(")).is_err());
+    }
+
+    fn mirror_config(token: Option<&str>) -> UserConfig {
+        let mirror = ReleaseConfig {
+            api_url: Some("https://git.example.com/api/v1".to_string()),
+            repo: Some("mirrors/7th-Heaven".to_string()),
+            asset_pattern: Some(r"^7thHeaven-.*\.exe$".to_string()),
+            token: token.map(str::to_string),
+        };
+        UserConfig {
+            release: [("7th_heaven".to_string(), mirror)].into(),
+            ..UserConfig::default()
+        }
+    }
+
+    fn bearer(source: &ReleaseSource) -> Option<&str> {
+        source
+            .authorization
+            .as_ref()
+            .map(|value| value.to_str().unwrap())
+    }
+
+    #[test]
+    fn release_config_is_per_loader() {
+        let answers = Answers::new(true);
+        let user = mirror_config(None);
+
+        let source = ReleaseSource::configured(&SeventhHeaven, &answers, &user, None).unwrap();
+        assert_eq!(source.api_url, "https://git.example.com/api/v1");
+        assert_eq!(source.repo, "mirrors/7th-Heaven");
+        assert!(source.asset_pattern.is_some());
+
+        let source = ReleaseSource::configured(&JunctionViii, &answers, &user, None).unwrap();
+        assert_eq!(source.api_url, DEFAULT_API_URL);
+        assert_eq!(source.repo, JunctionViii.repo());
+        assert!(source.asset_pattern.is_none());
+    }
+
+    #[test]
+    fn github_token_only_goes_to_github() {
+        let answers = Answers::new(true);
+        let env = || Some("from-env".to_string());
+
+        let mirror =
+            ReleaseSource::configured(&SeventhHeaven, &answers, &mirror_config(None), env());
+        assert_eq!(bearer(&mirror.unwrap()), None);
+        let user = UserConfig::default();
+        let github = ReleaseSource::configured(&SeventhHeaven, &answers, &user, env());
+        assert_eq!(bearer(&github.unwrap()), Some("Bearer from-env"));
+    }
+
+    #[test]
+    fn configured_token_wins_over_env() {
+        let mut answers = Answers::new(true);
+        let user = mirror_config(Some("from-config"));
+        let env = || Some("from-env".to_string());
+
+        let source = ReleaseSource::configured(&SeventhHeaven, &answers, &user, env()).unwrap();
+        assert_eq!(bearer(&source), Some("Bearer from-config"));
+        answers.set("token", "from-flag");
+        let source = ReleaseSource::configured(&SeventhHeaven, &answers, &user, env()).unwrap();
+        assert_eq!(bearer(&source), Some("Bearer from-flag"));
+    }
+}
@@ -1,8 +1,16 @@
+use crate::manifest::{self, FileKind};
 use anyhow::{Context, Result};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
 pub const LOGO_PNG: &[u8] = include_bytes!("../resources/logo.png");
+pub const GRID_PNG: &[u8] = include_bytes!("../resources/grid.png");
+pub const WIDE_GRID_PNG: &[u8] = include_bytes!("../resources/wide_grid.png");
+pub const HERO_PNG: &[u8] = include_bytes!("../resources/hero.png");
+pub const J8_LOGO_PNG: &[u8] = include_bytes!("../resources/junction_viii_logo.png");
+pub const J8_GRID_PNG: &[u8] = include_bytes!("../resources/junction_viii_grid.png");
+pub const J8_WIDE_GRID_PNG: &[u8] = include_bytes!("../resources/junction_viii_wide_grid.png");
+pub const J8_HERO_PNG: &[u8] = include_bytes!("../resources/junction_viii_hero.png");
 pub const TIMEOUT_EXE: &[u8] = include_bytes!("../resources/timeout.exe");
 
 pub const CONTROLLER_PROFILE: &str =
@@ -10,7 +18,10 @@ pub const CONTROLLER_PROFILE: &str =
 pub const MOD_XML: &str = include_str!("../resources/mod.xml");
 pub const SETTINGS_XML: &str = include_str!("../resources/settings.xml");
 pub const DXVK_CONF: &str = include_str!("../resources/dxvk.conf");
+pub const EDITIONS_TOML: &str = include_str!("../resources/editions.toml");
 pub const SHORTCUT_FILE: &str = include_str!("../resources/7th Heaven.desktop");
+pub const J8_SETTINGS_XML: &str = include_str!("../resources/junction_viii_settings.xml");
+pub const J8_SHORTCUT_FILE: &str = include_str!("../resources/Junction VIII.desktop");
 
 #[derive(Debug)]
 pub struct FileAsStr {
@@ -53,6 +64,12 @@ impl FileAsBytes {
                 file.write_all(&self.contents).with_context(|| {
                     format!("Couldn't write {} to {:?}", self.name, self.destination)
                 })?;
+                manifest::record_write(
+                    &self.destination,
+                    &self.contents,
+                    FileKind::Resource,
+                    false,
+                );
                 Ok(true)
             }
             Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
@@ -81,8 +98,8 @@ fn ensure_parent_dir(path: &Path) -> Result<()> {
 
 fn write_file(name: &str, destination: &Path, contents: &[u8]) -> Result<()> {
     ensure_parent_dir(destination)?;
-    std::fs::write(destination, contents)
-        .with_context(|| format!("Couldn't write {} to {:?}", name, destination))
+    manifest::write(destination, contents, FileKind::Resource)
+        .with_context(|| format!("Couldn't write {}", name))
 }
 
 pub fn as_bytes(name: String, destination: PathBuf, contents: &[u8]) -> FileAsBytes {
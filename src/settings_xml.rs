@@ -0,0 +1,519 @@
+use crate::manifest::{self, FileKind};
+use anyhow::{bail, Context, Result};
+use quick_xml::escape::{escape, unescape};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// A loader's `settings.xml`, edited in place so the elements MateriaForge doesn't manage keep
+/// whatever the loader or the user wrote to them
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub path: PathBuf,
+    xml: String,
+}
+
+/// A mod catalog the loader is subscribed to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Catalog {
+    /// Plain URL of the catalog, e.g. `https://example.com/catalog.xml`
+    pub url: String,
+    /// Name shown in the loader; defaults to the catalog's file name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Catalog {
+    pub fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let file = self.url.trim_end_matches('/').rsplit('/').next();
+        let file = file.unwrap_or(&self.url);
+        file.strip_suffix(".xml").unwrap_or(file).to_string()
+    }
+
+    pub fn matches(&self, url_or_name: &str) -> bool {
+        self.url == plain_url(url_or_name) || self.name().eq_ignore_ascii_case(url_or_name)
+    }
+}
+
+/// The `iros://Url/` link the loader stores a subscription to `url` as, e.g.
+/// `iros://Url/https$example.com/catalog.xml`
+pub fn iros_url(url: &str) -> String {
+    match url.starts_with("iros://") {
+        true => url.to_string(),
+        false => format!("iros://Url/{}", url.replacen("://", "$", 1)),
+    }
+}
+
+/// The plain URL behind an `iros://Url/` link
+pub fn plain_url(link: &str) -> String {
+    match link.strip_prefix("iros://Url/") {
+        Some(url) => url.replacen('$', "://", 1),
+        None => link.to_string(),
+    }
+}
+
+/// Where a child element is in a document
+enum Element {
+    /// The text between `<Name>` and `</Name>`
+    Content(Range<usize>),
+    /// A whole `<Name />`
+    Empty(Range<usize>),
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Result<Self> {
+        let xml = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        Ok(Settings {
+            path: path.to_path_buf(),
+            xml,
+        })
+    }
+
+    /// The settings in `path`, or `template` if there aren't any yet
+    pub fn load_or(path: &Path, template: &str) -> Result<Self> {
+        match path.is_file() {
+            true => Self::load(path),
+            false => Ok(Settings {
+                path: path.to_path_buf(),
+                xml: template.to_string(),
+            }),
+        }
+    }
+
+    /// The text of the `<Settings>` child `name`
+    pub fn get(&self, name: &str) -> Result<String> {
+        match self.find(name)? {
+            Element::Content(range) => Ok(unescape(&self.xml[range])
+                .with_context(|| format!("Invalid <{name}> in {}", self.path.display()))?
+                .trim()
+                .to_string()),
+            Element::Empty(_) => Ok(String::new()),
+        }
+    }
+
+    /// Set the text of the `<Settings>` child `name`, which must already exist
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        self.replace(name, &escape(value))
+    }
+
+    /// The `<string>` items of the list `name`, e.g. `SubscribedUrls`
+    pub fn get_list(&self, name: &str) -> Result<Vec<String>> {
+        let Element::Content(range) = self.find(name)? else {
+            return Ok(Vec::new());
+        };
+        let list = &self.xml[range];
+        let mut reader = Reader::from_str(list);
+        let mut items = Vec::new();
+        let mut item_start = None;
+        loop {
+            let before = reader.buffer_position() as usize;
+            match reader.read_event()? {
+                Event::Start(e) if e.name().as_ref() == b"string" => {
+                    item_start = Some(reader.buffer_position() as usize)
+                }
+                Event::End(e) if e.name().as_ref() == b"string" => {
+                    if let Some(start) = item_start.take() {
+                        items.push(unescape(&list[start..before])?.into_owned());
+                    }
+                }
+                Event::Eof => return Ok(items),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replace the `<string>` items of the list `name`
+    pub fn set_list(&mut self, name: &str, items: &[String]) -> Result<()> {
+        let items = items
+            .iter()
+            .map(|item| format!("<string>{}</string>", escape(item.as_str())))
+            .collect::<Vec<_>>();
+        self.set_children(name, &items)
+    }
+
+    /// Replace the children of `name` with `children`, one per line at the document's indent
+    pub fn set_children(&mut self, name: &str, children: &[String]) -> Result<()> {
+        let start = match self.find(name)? {
+            Element::Content(range) | Element::Empty(range) => range.start,
+        };
+        let line_start = self.xml[..start].rfind('\n').map_or(0, |i| i + 1);
+        let indent = self.xml[line_start..start]
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect::<String>();
+        let inner = match children.is_empty() {
+            true => String::new(),
+            false => {
+                let child_indent = format!("\n{indent}  ");
+                let lines = children
+                    .iter()
+                    .flat_map(|child| child.lines())
+                    .collect::<Vec<_>>()
+                    .join(&child_indent);
+                format!("{child_indent}{lines}\n{indent}")
+            }
+        };
+        self.replace(name, &inner)
+    }
+
+    /// The catalogs in `Subscriptions`, followed by any only listed in `SubscribedUrls`
+    pub fn catalogs(&self) -> Result<Vec<Catalog>> {
+        let mut catalogs = Vec::new();
+        for subscription in self.children("Subscriptions")? {
+            let Some(url) = child_text(&subscription, "Url")? else {
+                continue;
+            };
+            catalogs.push(Catalog {
+                url: plain_url(&url),
+                name: child_text(&subscription, "Name")?.filter(|name| !name.is_empty()),
+            });
+        }
+        for link in self.get_list("SubscribedUrls")? {
+            let url = plain_url(&link);
+            if !catalogs.iter().any(|catalog| catalog.url == url) {
+                catalogs.push(Catalog { url, name: None });
+            }
+        }
+        Ok(catalogs)
+    }
+
+    /// Subscribe to `catalog`, returning false if already subscribed to its URL
+    pub fn add_catalog(&mut self, catalog: &Catalog) -> Result<bool> {
+        if self.catalogs()?.iter().any(|c| c.url == catalog.url) {
+            return Ok(false);
+        }
+        let link = iros_url(&catalog.url);
+        let mut urls = self.get_list("SubscribedUrls")?;
+        urls.push(link.clone());
+        self.set_list("SubscribedUrls", &urls)?;
+
+        let mut subscriptions = self.children("Subscriptions")?;
+        subscriptions.push(format!(
+            "<Subscription>\n  <FailureCount>0</FailureCount>\n  <Url>{}</Url>\n  <Name>{}</Name>\n</Subscription>",
+            escape(link.as_str()),
+            escape(catalog.name().as_str())
+        ));
+        self.set_children("Subscriptions", &subscriptions)?;
+        Ok(true)
+    }
+
+    /// Unsubscribe from the catalogs whose URL or name is `url_or_name`, returning them
+    pub fn remove_catalog(&mut self, url_or_name: &str) -> Result<Vec<Catalog>> {
+        let removed = self
+            .catalogs()?
+            .into_iter()
+            .filter(|catalog| catalog.matches(url_or_name))
+            .collect::<Vec<_>>();
+        if removed.is_empty() {
+            return Ok(removed);
+        }
+        let is_removed = |link: &str| {
+            let url = plain_url(link);
+            removed.iter().any(|catalog| catalog.url == url)
+        };
+
+        let mut urls = self.get_list("SubscribedUrls")?;
+        urls.retain(|link| !is_removed(link));
+        self.set_list("SubscribedUrls", &urls)?;
+
+        let mut subscriptions = Vec::new();
+        for subscription in self.children("Subscriptions")? {
+            match child_text(&subscription, "Url")? {
+                Some(link) if is_removed(&link) => {}
+                _ => subscriptions.push(subscription),
+            }
+        }
+        self.set_children("Subscriptions", &subscriptions)?;
+        Ok(removed)
+    }
+
+    /// The child elements of `name` as XML, indented as if they started at column 0
+    fn children(&self, name: &str) -> Result<Vec<String>> {
+        let Element::Content(range) = self.find(name)? else {
+            return Ok(Vec::new());
+        };
+        let list = &self.xml[range];
+        let mut reader = Reader::from_str(list);
+        let mut children = Vec::new();
+        let mut depth = 0;
+        let mut child_start = 0;
+        loop {
+            let before = reader.buffer_position() as usize;
+            let child = match reader.read_event()? {
+                Event::Start(_) => {
+                    if depth == 0 {
+                        child_start = before;
+                    }
+                    depth += 1;
+                    continue;
+                }
+                Event::End(_) => {
+                    depth -= 1;
+                    if depth > 0 {
+                        continue;
+                    }
+                    child_start..reader.buffer_position() as usize
+                }
+                Event::Empty(_) if depth == 0 => before..reader.buffer_position() as usize,
+                Event::Eof => return Ok(children),
+                _ => continue,
+            };
+            let line_start = list[..child.start].rfind('\n').map_or(0, |i| i + 1);
+            let indent = &list[line_start..child.start];
+            children.push(
+                list[child]
+                    .lines()
+                    .map(|line| line.strip_prefix(indent).unwrap_or(line))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Couldn't create {}", parent.display()))?;
+        }
+        manifest::write(&self.path, self.xml.as_bytes(), FileKind::Resource)
+    }
+
+    fn replace(&mut self, name: &str, inner: &str) -> Result<()> {
+        match self.find(name)? {
+            Element::Content(range) => self.xml.replace_range(range, inner),
+            Element::Empty(range) => self
+                .xml
+                .replace_range(range, &format!("<{name}>{inner}</{name}>")),
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<Element> {
+        find_child(&self.xml, "Settings", name)
+            .with_context(|| format!("Couldn't parse {}", self.path.display()))?
+            .with_context(|| format!("{} has no <{name}>", self.path.display()))
+    }
+}
+
+/// The child `name` of the element `root` that `xml` holds
+fn find_child(xml: &str, root: &str, name: &str) -> Result<Option<Element>> {
+    let mut reader = Reader::from_str(xml);
+    let mut depth = 0;
+    let mut content_start = None;
+    loop {
+        let before = reader.buffer_position() as usize;
+        match reader.read_event()? {
+            Event::Start(e) => {
+                if depth == 0 && e.name().as_ref() != root.as_bytes() {
+                    bail!("Expected <{root}>");
+                }
+                if depth == 1 && e.name().as_ref() == name.as_bytes() {
+                    content_start = Some(reader.buffer_position() as usize);
+                }
+                depth += 1;
+            }
+            Event::End(_) => {
+                depth -= 1;
+                if let (1, Some(start)) = (depth, content_start) {
+                    return Ok(Some(Element::Content(start..before)));
+                }
+            }
+            Event::Empty(e) if depth == 1 && e.name().as_ref() == name.as_bytes() => {
+                return Ok(Some(Element::Empty(
+                    before..reader.buffer_position() as usize,
+                )));
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// The text of the child `name` of a `<Subscription>`
+fn child_text(subscription: &str, name: &str) -> Result<Option<String>> {
+    Ok(match find_child(subscription, "Subscription", name)? {
+        Some(Element::Content(range)) => Some(unescape(&subscription[range])?.trim().to_string()),
+        Some(Element::Empty(_)) => Some(String::new()),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource_handler::J8_SETTINGS_XML;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Settings xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <!-- written by the loader -->
+  <SubscribedUrls>
+    <string>iros://Url/https$example.com/a.xml</string>
+  </SubscribedUrls>
+  <Options><Opt a="1">x</Opt></Options>
+  <FF7Exe>C:\old.exe</FF7Exe>
+  <LibraryLocation />
+  <CurrentProfile>Default</CurrentProfile>
+</Settings>
+"#;
+
+    fn settings(xml: &str) -> Settings {
+        Settings {
+            path: PathBuf::from("settings.xml"),
+            xml: xml.to_string(),
+        }
+    }
+
+    #[test]
+    fn set_escapes_content_and_empty_elements() {
+        let mut s = settings(XML);
+        s.set("FF7Exe", "Z:\\games\\FF7 & <friends>.exe").unwrap();
+        s.set("LibraryLocation", "Z:\\mods\\\"a\"").unwrap();
+        assert!(s
+            .xml
+            .contains("<FF7Exe>Z:\\games\\FF7 &amp; &lt;friends&gt;.exe</FF7Exe>"));
+        assert!(s
+            .xml
+            .contains("<LibraryLocation>Z:\\mods\\&quot;a&quot;</LibraryLocation>"));
+        assert_eq!(s.get("FF7Exe").unwrap(), "Z:\\games\\FF7 & <friends>.exe");
+        assert_eq!(s.get("LibraryLocation").unwrap(), "Z:\\mods\\\"a\"");
+    }
+
+    #[test]
+    fn get_of_empty_element_is_empty() {
+        assert_eq!(settings(XML).get("LibraryLocation").unwrap(), "");
+    }
+
+    #[test]
+    fn missing_element_is_an_error() {
+        let mut s = settings(XML);
+        let error = s.set("FFNxUpdateChannel", "Canary").unwrap_err();
+        assert_eq!(error.to_string(), "settings.xml has no <FFNxUpdateChannel>");
+        assert!(s.get("FFNxUpdateChannel").is_err());
+        assert_eq!(s.xml, XML);
+    }
+
+    #[test]
+    fn set_list_indents_items() {
+        let mut s = settings(XML);
+        let items = vec!["one".to_string(), "a&b".to_string()];
+        s.set_list("SubscribedUrls", &items).unwrap();
+        assert!(s.xml.contains(
+            "  <SubscribedUrls>\n    <string>one</string>\n    <string>a&amp;b</string>\n  </SubscribedUrls>\n"
+        ));
+        assert_eq!(s.get_list("SubscribedUrls").unwrap(), items);
+
+        // An empty element becomes a list at the same indent
+        s.set_list("LibraryLocation", &items[..1]).unwrap();
+        assert!(s
+            .xml
+            .contains("  <LibraryLocation>\n    <string>one</string>\n  </LibraryLocation>\n"));
+
+        s.set_list("SubscribedUrls", &[]).unwrap();
+        assert!(s.xml.contains("  <SubscribedUrls></SubscribedUrls>\n"));
+        assert!(s.get_list("SubscribedUrls").unwrap().is_empty());
+    }
+
+    #[test]
+    fn round_trip_keeps_unmanaged_elements() {
+        let mut s = settings(XML);
+        let exe = s.get("FF7Exe").unwrap();
+        let urls = s.get_list("SubscribedUrls").unwrap();
+        s.set("FF7Exe", "Z:\\new.exe").unwrap();
+        s.set("FF7Exe", &exe).unwrap();
+        s.set_list("SubscribedUrls", &urls).unwrap();
+        assert_eq!(s.xml, XML);
+    }
+
+    fn catalog(url: &str, name: Option<&str>) -> Catalog {
+        Catalog {
+            url: url.to_string(),
+            name: name.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn iros_links() {
+        let url = "https://example.com/catalogs/a$b.xml";
+        assert_eq!(
+            iros_url(url),
+            "iros://Url/https$example.com/catalogs/a$b.xml"
+        );
+        assert_eq!(plain_url(&iros_url(url)), url);
+        assert_eq!(iros_url(&iros_url(url)), iros_url(url));
+        assert_eq!(plain_url(url), url);
+    }
+
+    #[test]
+    fn add_catalog_subscribes_once() {
+        let mut s = settings(J8_SETTINGS_XML);
+        let qhimm = catalog("https://example.com/qhimm.xml", Some("Qhimm & co"));
+        assert!(s.add_catalog(&qhimm).unwrap());
+        assert!(s
+            .add_catalog(&catalog("https://example.com/sega.xml", None))
+            .unwrap());
+        // The same URL under another name
+        assert!(!s
+            .add_catalog(&catalog("https://example.com/qhimm.xml", Some("Other")))
+            .unwrap());
+
+        assert_eq!(
+            s.get_list("SubscribedUrls").unwrap(),
+            [
+                "iros://Url/https$example.com/qhimm.xml",
+                "iros://Url/https$example.com/sega.xml",
+            ]
+        );
+        assert!(s.xml.contains(
+            "  <Subscriptions>\n    <Subscription>\n      <FailureCount>0</FailureCount>\n      \
+             <Url>iros://Url/https$example.com/qhimm.xml</Url>\n      \
+             <Name>Qhimm &amp; co</Name>\n    </Subscription>\n"
+        ));
+        assert_eq!(
+            s.catalogs().unwrap(),
+            [qhimm, catalog("https://example.com/sega.xml", Some("sega"))]
+        );
+    }
+
+    #[test]
+    fn remove_catalog_by_name_or_url() {
+        let mut s = settings(J8_SETTINGS_XML);
+        for name in ["qhimm", "sega", "tsunamods"] {
+            s.add_catalog(&catalog(&format!("https://example.com/{name}.xml"), None))
+                .unwrap();
+        }
+
+        let removed = s.remove_catalog("SEGA").unwrap();
+        assert_eq!(
+            removed,
+            [catalog("https://example.com/sega.xml", Some("sega"))]
+        );
+        let removed = s
+            .remove_catalog("iros://Url/https$example.com/qhimm.xml")
+            .unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(s
+            .remove_catalog("https://example.com/qhimm.xml")
+            .unwrap()
+            .is_empty());
+
+        let remaining = "iros://Url/https$example.com/tsunamods.xml";
+        assert_eq!(s.get_list("SubscribedUrls").unwrap(), [remaining]);
+        assert_eq!(s.children("Subscriptions").unwrap().len(), 1);
+        assert_eq!(s.catalogs().unwrap().len(), 1);
+
+        s.remove_catalog("tsunamods").unwrap();
+        assert!(s.get_list("SubscribedUrls").unwrap().is_empty());
+        assert!(s.children("Subscriptions").unwrap().is_empty());
+        assert!(s
+            .get_list("ExtraFolders")
+            .unwrap()
+            .contains(&"music".to_string()));
+    }
+}